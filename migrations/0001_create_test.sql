@@ -1,4 +1,5 @@
 CREATE TABLE IF NOT EXISTS test (
   id serial PRIMARY KEY,
+  -- keep in sync with validation::TXT_MAX_CHARS
   txt varchar(255) NOT NULL
 );
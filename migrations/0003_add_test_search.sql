@@ -0,0 +1,4 @@
+ALTER TABLE test ADD COLUMN IF NOT EXISTS txt_search tsvector
+  GENERATED ALWAYS AS (to_tsvector('simple', txt)) STORED;
+
+CREATE INDEX IF NOT EXISTS test_txt_search_idx ON test USING GIN (txt_search);
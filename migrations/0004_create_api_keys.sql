@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS api_keys (
+  id serial PRIMARY KEY,
+  name varchar(255) NOT NULL,
+  -- sha256 of the key; the key itself is never stored
+  key_hash bytea NOT NULL UNIQUE,
+  scope varchar(16) NOT NULL CHECK (scope IN ('read', 'read_write')),
+  created_at timestamptz NOT NULL DEFAULT now(),
+  revoked_at timestamptz
+);
@@ -0,0 +1,4 @@
+-- JWT subject owning the row; '' is the shared owner used by API keys.
+ALTER TABLE test ADD COLUMN IF NOT EXISTS owner_id varchar(255) NOT NULL DEFAULT '';
+
+CREATE INDEX IF NOT EXISTS test_owner_id_idx ON test (owner_id, id);
@@ -0,0 +1,4 @@
+-- Deleted rows stay in the trash until restored or purged.
+ALTER TABLE test ADD COLUMN IF NOT EXISTS deleted_at timestamptz;
+
+CREATE INDEX IF NOT EXISTS test_deleted_at_idx ON test (deleted_at) WHERE deleted_at IS NOT NULL;
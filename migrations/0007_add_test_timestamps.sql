@@ -0,0 +1,6 @@
+-- Existing rows get the time of the migration.
+ALTER TABLE test
+    ADD COLUMN IF NOT EXISTS created_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN IF NOT EXISTS updated_at timestamptz NOT NULL DEFAULT now();
+
+CREATE INDEX IF NOT EXISTS test_owner_id_updated_at_idx ON test (owner_id, updated_at);
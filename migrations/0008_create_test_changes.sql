@@ -0,0 +1,69 @@
+-- Changelog behind GET /txt/events. Every write to a live row is recorded
+-- with the row as it was written; purging the trash is not.
+CREATE TABLE IF NOT EXISTS test_changes (
+    id bigserial PRIMARY KEY,
+    owner_id varchar(255) NOT NULL,
+    kind varchar(16) NOT NULL CHECK (kind IN ('created', 'updated', 'deleted')),
+    test jsonb NOT NULL,
+    changed_at timestamptz NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS test_changes_owner_id_idx ON test_changes (owner_id, id);
+
+CREATE OR REPLACE FUNCTION record_test_change() RETURNS trigger AS $$
+DECLARE
+    changed test;
+    change_kind varchar(16);
+    change_id bigint;
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        changed := NEW;
+        change_kind := 'created';
+    ELSIF TG_OP = 'DELETE' THEN
+        IF OLD.deleted_at IS NOT NULL THEN
+            RETURN NULL;
+        END IF;
+        changed := OLD;
+        change_kind := 'deleted';
+    ELSIF OLD.deleted_at IS NULL AND NEW.deleted_at IS NOT NULL THEN
+        changed := NEW;
+        change_kind := 'deleted';
+    ELSIF OLD.deleted_at IS NOT NULL AND NEW.deleted_at IS NULL THEN
+        -- Restored from the trash.
+        changed := NEW;
+        change_kind := 'created';
+    ELSIF NEW.deleted_at IS NULL THEN
+        changed := NEW;
+        change_kind := 'updated';
+    ELSE
+        RETURN NULL;
+    END IF;
+
+    -- Ids must become visible in order, or a reader could move past one
+    -- that commits later. Writers queue here until the previous one commits.
+    PERFORM pg_advisory_xact_lock(hashtext('test_changes'));
+
+    INSERT INTO test_changes (owner_id, kind, test)
+    VALUES (
+        changed.owner_id,
+        change_kind,
+        jsonb_build_object(
+            'id', changed.id,
+            'txt', changed.txt,
+            'version', changed.version,
+            'created_at', changed.created_at,
+            'updated_at', changed.updated_at
+        )
+    )
+    RETURNING id INTO change_id;
+
+    -- Keep the last 10000 changes (events::CHANGELOG_CAPACITY).
+    DELETE FROM test_changes WHERE id <= change_id - 10000;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS test_changes_trigger ON test;
+CREATE TRIGGER test_changes_trigger
+    AFTER INSERT OR UPDATE OR DELETE ON test
+    FOR EACH ROW EXECUTE FUNCTION record_test_change();
@@ -0,0 +1,14 @@
+-- Tell every instance listening on `test_changes` that the changelog has
+-- grown. The payload is the latest change id; listeners read the changes
+-- themselves, so notifications that are dropped or merged lose nothing.
+CREATE OR REPLACE FUNCTION notify_test_changes() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('test_changes', coalesce(max(id), 0)::text) FROM test_changes;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS test_changes_notify_trigger ON test;
+CREATE TRIGGER test_changes_notify_trigger
+    AFTER INSERT OR UPDATE OR DELETE ON test
+    FOR EACH STATEMENT EXECUTE FUNCTION notify_test_changes();
@@ -0,0 +1,47 @@
+//! Operator endpoints under `/admin`, behind a bearer token of their own.
+
+use std::sync::Arc;
+
+use axum::{extract::State, http::HeaderMap, Json};
+use serde_json::Value;
+
+use crate::{
+    auth::{bearer_token, hash_key},
+    config::Secret,
+    error::AppError,
+};
+
+#[derive(Debug, Clone)]
+pub struct AdminConfig {
+    /// Token `/admin` requests must send as `Authorization: Bearer`.
+    pub token: Secret,
+    /// What `/admin/config` serves: the effective configuration, with
+    /// secrets redacted.
+    pub config: Value,
+}
+
+/// The configuration the service runs with, secrets redacted.
+#[utoipa::path(
+    get,
+    path = "/admin/config",
+    tag = "admin",
+    responses(
+        (status = 200, description = "The effective configuration, with secrets redacted", body = Object),
+        (status = 401, description = "Missing or invalid admin token", body = ErrorBody),
+        (status = 404, description = "No `ADMIN_TOKEN` is configured", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn config(
+    State(admin): State<Option<Arc<AdminConfig>>>,
+    headers: HeaderMap,
+) -> Result<Json<Value>, AppError> {
+    let admin = admin.ok_or_else(|| AppError::not_found("the admin API is disabled"))?;
+    let token =
+        bearer_token(&headers)?.ok_or(AppError::Unauthorized("an admin token is required"))?;
+    // Comparing digests keeps the comparison from leaking the token.
+    if hash_key(token) != hash_key(admin.token.expose()) {
+        return Err(AppError::Unauthorized("the admin token is invalid"));
+    }
+    Ok(Json(admin.config.clone()))
+}
@@ -0,0 +1,209 @@
+//! Bearer authentication for the `/txt` routes, with either an API key or
+//! a user JWT.
+
+use std::{convert::Infallible, str::FromStr};
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, State},
+    http::{header::AUTHORIZATION, request::Parts, HeaderMap, Method, Request},
+    middleware::Next,
+    response::Response,
+};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::Settings,
+    error::AppError,
+    jwt::{JwtConfig, JwtVerifier},
+    repository::ApiKeyRepository,
+};
+
+/// What an API key may do. Read-write keys can also read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Scope {
+    Read,
+    ReadWrite,
+}
+
+impl Scope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::ReadWrite => "read_write",
+        }
+    }
+
+    /// Scope a request needs, based on whether its method mutates.
+    pub(crate) fn required_for(method: &Method) -> Self {
+        match *method {
+            Method::GET | Method::HEAD | Method::OPTIONS => Scope::Read,
+            _ => Scope::ReadWrite,
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(scope: &str) -> Result<Self, Self::Err> {
+        match scope {
+            "read" => Ok(Scope::Read),
+            "read_write" => Ok(Scope::ReadWrite),
+            scope => Err(format!("unknown API key scope {scope:?}")),
+        }
+    }
+}
+
+/// An active key from the `api_keys` table.
+#[derive(Debug, Clone)]
+pub struct ApiKey {
+    pub id: i32,
+    pub name: String,
+    pub scope: Scope,
+}
+
+/// Hashes a key the way it is stored in `api_keys.key_hash`.
+///
+/// Keys are random tokens rather than passwords, so a fast hash is enough.
+pub fn hash_key(key: &str) -> Vec<u8> {
+    Sha256::digest(key.as_bytes()).to_vec()
+}
+
+/// Owner of the rows a request can see and change.
+///
+/// Requests with a user JWT act for its subject. API key and anonymous
+/// requests share the owner with an empty id, so they never see user rows.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Owner(String);
+
+impl Owner {
+    pub fn shared() -> Self {
+        Owner(String::new())
+    }
+
+    pub fn user(subject: impl Into<String>) -> Self {
+        Owner(subject.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Owner {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<Owner>()
+            .cloned()
+            .unwrap_or_else(Owner::shared))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AuthConfig {
+    /// Let requests that only read through without a key.
+    pub public_reads: bool,
+    /// Accept user JWTs besides API keys.
+    pub jwt: Option<JwtVerifier>,
+}
+
+impl AuthConfig {
+    /// Reads `AUTH_PUBLIC_READS` and the `JWT_*` settings.
+    pub(crate) fn read(settings: &mut Settings) -> Self {
+        let public_reads = settings.parse("AUTH_PUBLIC_READS", false);
+        let jwt = JwtVerifier::new(&JwtConfig {
+            hs256_secret: settings.get("JWT_HS256_SECRET"),
+            rs256_public_key: settings.get("JWT_RS256_PUBLIC_KEY"),
+            issuer: settings.get("JWT_ISSUER"),
+            audience: settings.get("JWT_AUDIENCE"),
+        })
+        .unwrap_or_else(|error| {
+            settings.invalid(format!("JWT_RS256_PUBLIC_KEY: {error}"));
+            None
+        });
+        AuthConfig { public_reads, jwt }
+    }
+}
+
+/// State of the [`authenticate`] middleware.
+#[derive(Debug, Clone)]
+pub struct AuthState<R> {
+    repo: R,
+    config: AuthConfig,
+}
+
+impl<R> AuthState<R> {
+    pub fn new(repo: R, config: AuthConfig) -> Self {
+        AuthState { repo, config }
+    }
+}
+
+/// Authenticates the bearer token and adds the caller's [`Owner`] to the
+/// request extensions.
+///
+/// When JWTs are configured, tokens shaped like a JWT are verified as one
+/// and may read and write their subject's rows. Other tokens are checked
+/// against `api_keys`, and their scope against the request method; the
+/// matched [`ApiKey`] is added to the extensions too.
+pub async fn authenticate<R: ApiKeyRepository, B>(
+    State(state): State<AuthState<R>>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Result<Response, AppError> {
+    let required = Scope::required_for(req.method());
+    let token = bearer_token(req.headers())?;
+
+    if token.is_none() && required == Scope::Read && state.config.public_reads {
+        req.extensions_mut().insert(Owner::shared());
+        return Ok(next.run(req).await);
+    }
+
+    let token = token.ok_or(AppError::Unauthorized("an API key is required"))?;
+    if let Some(jwt) = &state.config.jwt {
+        if JwtVerifier::looks_like_jwt(token) {
+            let owner = Owner::user(jwt.verify(token)?);
+            req.extensions_mut().insert(owner);
+            return Ok(next.run(req).await);
+        }
+    }
+
+    let key = state
+        .repo
+        .find_api_key(&hash_key(token))
+        .await?
+        .ok_or(AppError::Unauthorized("the API key is invalid or revoked"))?;
+    if key.scope < required {
+        return Err(AppError::Forbidden("the API key is not allowed to write"));
+    }
+
+    req.extensions_mut().insert(key);
+    req.extensions_mut().insert(Owner::shared());
+    Ok(next.run(req).await)
+}
+
+/// The token of a `Bearer` `Authorization` header, if one is sent.
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Result<Option<&str>, AppError> {
+    let Some(value) = headers.get(AUTHORIZATION) else {
+        return Ok(None);
+    };
+    let value = value
+        .to_str()
+        .map_err(|_| AppError::Unauthorized("malformed Authorization header"))?;
+    match value.split_once(' ') {
+        Some((scheme, token))
+            if scheme.eq_ignore_ascii_case("bearer") && !token.trim().is_empty() =>
+        {
+            Ok(Some(token.trim()))
+        }
+        _ => Err(AppError::Unauthorized(
+            "the Authorization header must use the Bearer scheme",
+        )),
+    }
+}
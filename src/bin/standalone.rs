@@ -0,0 +1,90 @@
+//! Serves the API without the Shuttle runtime.
+//!
+//! Configured through the environment:
+//! - `DATABASE_URL`: Postgres connection string (required)
+//! - `HOST`: address to bind to, defaults to `0.0.0.0`
+//! - `PORT`: port to bind to, defaults to `8000`
+//! - `DATABASE_MAX_CONNECTIONS`, `DATABASE_MIN_CONNECTIONS`,
+//!   `DATABASE_ACQUIRE_TIMEOUT_SECS`, `DATABASE_STATEMENT_TIMEOUT_SECS`: see
+//!   [`app::config::DatabaseConfig`]
+//! - `ADMIN_TOKEN`: bearer token for `/admin/config`, disabled without one
+//! - `AUTH_PUBLIC_READS`: serve `GET /txt` routes without an API key,
+//!   defaults to `false`
+//! - `JWT_HS256_SECRET` / `JWT_RS256_PUBLIC_KEY`: accept user JWTs signed
+//!   with this secret or PEM public key, scoping rows to their `sub`
+//! - `JWT_ISSUER`, `JWT_AUDIENCE`: required `iss` and `aud` claims, if set
+//! - `RATE_LIMIT_ENABLED`, `RATE_LIMIT_{READ,WRITE}_{BURST,PER_MINUTE}`,
+//!   `RATE_LIMIT_TRUST_PROXY`: see [`app::rate_limit::RateLimitConfig`]
+//! - `TRASH_RETENTION_DAYS`: days deleted rows stay restorable, defaults
+//!   to `30`
+//! - `API_DOCS`: serve a Redoc page at `/docs`, defaults to `false`
+//! - `CORS_ALLOWED_ORIGINS`, `COMPRESSION_ENABLED`, `DECOMPRESSION_ENABLED`,
+//!   `REQUEST_TIMEOUT_SECS`, `MAX_BODY_BYTES`: see
+//!   [`app::layers::LayerConfig`]
+//! - `RUST_LOG`: log filter, defaults to `info,sqlx=warn`
+//! - `OTEL_EXPORTER_OTLP_ENDPOINT`: OTLP/gRPC collector to export spans to,
+//!   such as `http://localhost:4317`; spans are logged to stdout as JSON
+//!   without one
+//! - `OTEL_SERVICE_NAME`: service name reported to the collector, defaults
+//!   to `shuttle-axum`
+
+use std::{env, error::Error, net::SocketAddr};
+
+use app::{config::AppConfig, migrate, repository::PgRepository, router, telemetry, trash};
+use shuttle_runtime::tracing;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let config = AppConfig::standalone_from_lookup(|key| env::var(key).ok())?;
+    let server = config
+        .server
+        .clone()
+        .expect("standalone config has a server");
+    let telemetry = config
+        .telemetry
+        .as_ref()
+        .expect("standalone config has telemetry");
+    telemetry::init(telemetry)?;
+
+    let addr = server.addr();
+    let pool = config.database.connect(server.connect_options()).await?;
+    migrate::run(&pool).await?;
+    let repo = PgRepository::new(pool);
+    repo.spawn_listener().await?;
+    trash::spawn_purge(repo.clone(), config.purge);
+    let router = router(repo, config.router_config()).await;
+
+    tracing::info!(%addr, "Starting axum server");
+    axum::Server::try_bind(&addr)?
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
+    tracing::info!("Server stopped");
+    telemetry::shutdown();
+    Ok(())
+}
+
+/// Resolves on SIGTERM or Ctrl+C so in-flight requests can finish.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("failed to listen for Ctrl+C");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+    tracing::info!("Shutdown signal received, draining connections");
+}
@@ -0,0 +1,206 @@
+use std::collections::BTreeSet;
+
+use axum::{
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        Query, State,
+    },
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    auth::Owner,
+    error::AppError,
+    repository::{DeleteManyOutcome, TestRepository},
+    validation::{self, FieldError},
+    Test,
+};
+
+/// Largest batch accepted by the bulk endpoints.
+pub const MAX_BULK_ITEMS: usize = 1000;
+
+/// How a bulk request treats items that cannot be applied.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkMode {
+    /// Apply every item or none of them.
+    #[default]
+    Atomic,
+    /// Apply the items that can be applied and report the rest.
+    BestEffort,
+}
+
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct BulkParams {
+    #[serde(default)]
+    #[param(inline)]
+    mode: BulkMode,
+}
+
+/// Result of a single item in a best-effort bulk request.
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ItemOutcome {
+    Created {
+        index: usize,
+        id: i32,
+    },
+    Deleted {
+        index: usize,
+        id: i32,
+    },
+    Invalid {
+        index: usize,
+        errors: Vec<FieldError>,
+    },
+    NotFound {
+        index: usize,
+        id: i32,
+    },
+}
+
+/// Response of the bulk endpoints: the affected rows and, in best-effort
+/// mode, the outcome of every submitted item in request order.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BulkResponse {
+    items: Vec<Test>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outcomes: Option<Vec<ItemOutcome>>,
+}
+
+fn check_batch_size(len: usize) -> Result<(), AppError> {
+    if len > MAX_BULK_ITEMS {
+        return Err(AppError::bad_request(format!(
+            "bulk requests accept at most {MAX_BULK_ITEMS} items"
+        )));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/txt/bulk",
+    tag = "txt",
+    params(BulkParams),
+    request_body(content = Vec<String>, description = "Up to 1000 `txt` values"),
+    responses(
+        (status = 200, description = "The created tests", body = BulkResponse),
+        (status = 400, description = "Too many items", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "The API key is read-only", body = ErrorBody),
+        (status = 422, description = "An item is invalid, in `atomic` mode", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn create_tests<R: TestRepository>(
+    State(repo): State<R>,
+    owner: Owner,
+    params: Result<Query<BulkParams>, QueryRejection>,
+    payload: Result<Json<Vec<String>>, JsonRejection>,
+) -> Result<Json<BulkResponse>, AppError> {
+    let Query(params) = params?;
+    let Json(txts) = payload?;
+    check_batch_size(txts.len())?;
+
+    let validated: Vec<_> = txts
+        .iter()
+        .map(|txt| validation::validate_txt(txt))
+        .collect();
+
+    if params.mode == BulkMode::Atomic {
+        let errors: Vec<_> = validated
+            .iter()
+            .enumerate()
+            .filter_map(|(index, result)| {
+                let error = result.as_ref().err()?;
+                Some(FieldError::new(format!("[{index}]"), error.message.clone()))
+            })
+            .collect();
+        if !errors.is_empty() {
+            return Err(AppError::Validation(errors));
+        }
+    }
+
+    let valid: Vec<&str> = validated
+        .iter()
+        .filter_map(|result| result.as_deref().ok())
+        .collect();
+    let items = repo.create_many(&owner, &valid).await?;
+
+    let outcomes = (params.mode == BulkMode::BestEffort).then(|| {
+        let mut inserted = items.iter();
+        validated
+            .into_iter()
+            .enumerate()
+            .map(|(index, result)| match result {
+                Ok(_) => ItemOutcome::Created {
+                    index,
+                    id: inserted.next().expect("one row per valid item").id,
+                },
+                Err(error) => ItemOutcome::Invalid {
+                    index,
+                    errors: vec![error],
+                },
+            })
+            .collect()
+    });
+
+    Ok(Json(BulkResponse { items, outcomes }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/txt/bulk",
+    tag = "txt",
+    params(BulkParams),
+    request_body(content = Vec<i32>, description = "Up to 1000 test ids"),
+    responses(
+        (status = 200, description = "The tests, now in the trash", body = BulkResponse),
+        (status = 400, description = "Too many items", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "The API key is read-only", body = ErrorBody),
+        (status = 404, description = "An id does not exist, in `atomic` mode", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn delete_tests<R: TestRepository>(
+    State(repo): State<R>,
+    owner: Owner,
+    params: Result<Query<BulkParams>, QueryRejection>,
+    payload: Result<Json<Vec<i32>>, JsonRejection>,
+) -> Result<Json<BulkResponse>, AppError> {
+    let Query(params) = params?;
+    let Json(ids) = payload?;
+    check_batch_size(ids.len())?;
+
+    let all_or_nothing = params.mode == BulkMode::Atomic;
+    let items = match repo.delete_many(&owner, &ids, all_or_nothing).await? {
+        DeleteManyOutcome::Deleted(items) => items,
+        DeleteManyOutcome::Missing(missing) => {
+            let missing: Vec<String> = missing.iter().map(ToString::to_string).collect();
+            return Err(AppError::not_found(format!(
+                "tests not found: {}",
+                missing.join(", ")
+            )));
+        }
+    };
+
+    let deleted: BTreeSet<i32> = items.iter().map(|test| test.id).collect();
+    let outcomes = (params.mode == BulkMode::BestEffort).then(|| {
+        ids.iter()
+            .enumerate()
+            .map(|(index, &id)| {
+                if deleted.contains(&id) {
+                    ItemOutcome::Deleted { index, id }
+                } else {
+                    ItemOutcome::NotFound { index, id }
+                }
+            })
+            .collect()
+    });
+
+    Ok(Json(BulkResponse { items, outcomes }))
+}
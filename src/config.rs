@@ -0,0 +1,360 @@
+//! Startup configuration, read from Shuttle secrets or, for the standalone
+//! binary, the environment.
+//!
+//! Each section reads its keys through [`Settings`], which keeps defaults
+//! for unset keys and collects every invalid one. [`AppConfig::from_lookup`]
+//! validates all sections at once, so a broken deployment reports all its
+//! mistakes in a single [`ConfigError`] instead of one per restart.
+
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::{Serialize, Serializer};
+use sqlx::{
+    postgres::{PgConnectOptions, PgPoolOptions},
+    PgPool,
+};
+
+use crate::{admin::AdminConfig, telemetry::TelemetryConfig, trash::PurgeConfig, RouterConfig};
+
+/// Stands in for secrets in `Debug` output and at `/admin/config`.
+pub(crate) const REDACTED: &str = "<redacted>";
+
+/// Shortest `ADMIN_TOKEN` accepted, so it cannot be guessed.
+const MIN_ADMIN_TOKEN_LEN: usize = 16;
+
+/// Longest `statement_timeout` Postgres accepts, which it keeps as an `i32`
+/// of milliseconds.
+const MAX_STATEMENT_TIMEOUT_SECS: u64 = i32::MAX as u64 / 1000;
+
+/// Every invalid setting found while reading the configuration.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ConfigError(pub Vec<String>);
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid configuration:")?;
+        for error in &self.0 {
+            write!(f, "\n- {error}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Same as `Display`, which is what a `main` returning this error prints.
+impl fmt::Debug for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// A setting that must not be shown, such as a token.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(secret: impl Into<String>) -> Self {
+        Secret(secret.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+/// Serializes a duration as whole seconds.
+pub(crate) fn secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_secs())
+}
+
+/// Serializes an optional duration as whole seconds, or `null`.
+pub(crate) fn opt_secs<S: Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    duration
+        .map(|duration| duration.as_secs())
+        .serialize(serializer)
+}
+
+/// Reads settings through a lookup backed by Shuttle secrets or the
+/// environment, collecting what is invalid.
+pub(crate) struct Settings<'a> {
+    lookup: &'a dyn Fn(&str) -> Option<String>,
+    errors: Vec<String>,
+}
+
+impl<'a> Settings<'a> {
+    pub(crate) fn new(lookup: &'a dyn Fn(&str) -> Option<String>) -> Self {
+        Settings {
+            lookup,
+            errors: Vec::new(),
+        }
+    }
+
+    /// The raw value of `key`, if set.
+    pub(crate) fn get(&self, key: &str) -> Option<String> {
+        (self.lookup)(key)
+    }
+
+    /// Parses `key`, keeping `default` when it is unset or invalid.
+    pub(crate) fn parse<T: FromStr>(&mut self, key: &str, default: T) -> T {
+        match self.get(key) {
+            Some(value) => value.parse().unwrap_or_else(|_| {
+                self.invalid(format!("{key} has an invalid value {value:?}"));
+                default
+            }),
+            None => default,
+        }
+    }
+
+    /// Records an invalid setting.
+    pub(crate) fn invalid(&mut self, error: impl Into<String>) {
+        self.errors.push(error.into());
+    }
+
+    /// Records `error` unless `valid`.
+    pub(crate) fn check(&mut self, valid: bool, error: &str) {
+        if !valid {
+            self.invalid(error);
+        }
+    }
+
+    /// `config`, or every invalid setting recorded while reading it.
+    pub(crate) fn finish<T>(self, config: T) -> Result<T, ConfigError> {
+        if self.errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError(self.errors))
+        }
+    }
+}
+
+/// Reads a section with `read` through `lookup`.
+fn read<T>(
+    lookup: impl Fn(&str) -> Option<String>,
+    read: impl FnOnce(&mut Settings) -> T,
+) -> Result<T, ConfigError> {
+    let mut settings = Settings::new(&lookup);
+    let config = read(&mut settings);
+    settings.finish(config)
+}
+
+/// Size of the connection pool and limits on its connections.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DatabaseConfig {
+    pub max_connections: u32,
+    /// Connections kept open while idle.
+    pub min_connections: u32,
+    /// Time a query may wait for a free connection.
+    #[serde(rename = "acquire_timeout_secs", serialize_with = "secs")]
+    pub acquire_timeout: Duration,
+    /// Postgres `statement_timeout` of every connection, or no limit.
+    #[serde(rename = "statement_timeout_secs", serialize_with = "opt_secs")]
+    pub statement_timeout: Option<Duration>,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            max_connections: 10,
+            min_connections: 0,
+            acquire_timeout: Duration::from_secs(30),
+            statement_timeout: None,
+        }
+    }
+}
+
+impl DatabaseConfig {
+    /// Reads `DATABASE_MAX_CONNECTIONS`, `DATABASE_MIN_CONNECTIONS`,
+    /// `DATABASE_ACQUIRE_TIMEOUT_SECS` and
+    /// `DATABASE_STATEMENT_TIMEOUT_SECS` (`0` for no limit).
+    pub(crate) fn read(settings: &mut Settings) -> Self {
+        let defaults = DatabaseConfig::default();
+        let max_connections = settings.parse("DATABASE_MAX_CONNECTIONS", defaults.max_connections);
+        settings.check(
+            max_connections > 0,
+            "DATABASE_MAX_CONNECTIONS must be positive",
+        );
+        let min_connections = settings.parse("DATABASE_MIN_CONNECTIONS", defaults.min_connections);
+        settings.check(
+            min_connections <= max_connections,
+            "DATABASE_MIN_CONNECTIONS must not exceed DATABASE_MAX_CONNECTIONS",
+        );
+        let acquire_secs = settings.parse(
+            "DATABASE_ACQUIRE_TIMEOUT_SECS",
+            defaults.acquire_timeout.as_secs(),
+        );
+        settings.check(
+            acquire_secs > 0,
+            "DATABASE_ACQUIRE_TIMEOUT_SECS must be positive",
+        );
+        let statement_secs = settings.parse("DATABASE_STATEMENT_TIMEOUT_SECS", 0);
+        if statement_secs > MAX_STATEMENT_TIMEOUT_SECS {
+            settings.invalid(format!(
+                "DATABASE_STATEMENT_TIMEOUT_SECS must be at most {MAX_STATEMENT_TIMEOUT_SECS}"
+            ));
+        }
+        let statement_timeout = match statement_secs.min(MAX_STATEMENT_TIMEOUT_SECS) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        };
+        DatabaseConfig {
+            max_connections,
+            min_connections,
+            acquire_timeout: Duration::from_secs(acquire_secs),
+            statement_timeout,
+        }
+    }
+
+    /// Opens a pool to the database `options` point to.
+    pub async fn connect(&self, mut options: PgConnectOptions) -> Result<PgPool, sqlx::Error> {
+        if let Some(timeout) = self.statement_timeout {
+            let millis = timeout.as_millis().to_string();
+            options = options.options([("statement_timeout", millis.as_str())]);
+        }
+        PgPoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(self.min_connections)
+            .acquire_timeout(self.acquire_timeout)
+            .connect_with(options)
+            .await
+    }
+}
+
+/// Where the standalone binary listens and which database it uses. Shuttle
+/// provides both itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ServerConfig {
+    /// Postgres connection string, which may hold a password.
+    pub database_url: Secret,
+    pub host: IpAddr,
+    pub port: u16,
+}
+
+impl ServerConfig {
+    /// Reads `DATABASE_URL` (required), `HOST` and `PORT`.
+    pub(crate) fn read(settings: &mut Settings) -> Self {
+        let database_url = settings.get("DATABASE_URL").unwrap_or_default();
+        if database_url.is_empty() {
+            settings.invalid("DATABASE_URL must be set");
+        } else if database_url.parse::<PgConnectOptions>().is_err() {
+            settings.invalid("DATABASE_URL is not a valid Postgres connection string");
+        }
+        ServerConfig {
+            database_url: Secret::new(database_url),
+            host: settings.parse("HOST", [0, 0, 0, 0].into()),
+            port: settings.parse("PORT", 8000),
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.host, self.port)
+    }
+
+    pub fn connect_options(&self) -> PgConnectOptions {
+        self.database_url
+            .expose()
+            .parse()
+            .expect("DATABASE_URL is validated when read")
+    }
+}
+
+/// Everything the service reads at startup.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AppConfig {
+    pub database: DatabaseConfig,
+    pub router: RouterConfig,
+    pub purge: PurgeConfig,
+    /// Only read for the standalone binary: Shuttle installs its own
+    /// subscriber.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub telemetry: Option<TelemetryConfig>,
+    /// Bearer token for `/admin/config`, which answers `404` without one.
+    pub admin_token: Option<Secret>,
+    /// Only read for the standalone binary.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub server: Option<ServerConfig>,
+}
+
+impl AppConfig {
+    /// Reads the [`DatabaseConfig`], [`RouterConfig`] and [`PurgeConfig`]
+    /// settings and `ADMIN_TOKEN` through `lookup`, failing with every
+    /// invalid one.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        read(lookup, AppConfig::read)
+    }
+
+    /// [`AppConfig::from_lookup`] with the [`TelemetryConfig`] and
+    /// [`ServerConfig`] of the standalone binary too.
+    pub fn standalone_from_lookup(
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        read(lookup, |settings| {
+            let config = AppConfig::read(settings);
+            AppConfig {
+                telemetry: Some(TelemetryConfig::read(settings)),
+                server: Some(ServerConfig::read(settings)),
+                ..config
+            }
+        })
+    }
+
+    fn read(settings: &mut Settings) -> Self {
+        let database = DatabaseConfig::read(settings);
+        let router = RouterConfig::read(settings);
+        let purge = PurgeConfig::read(settings);
+        let admin_token = settings.get("ADMIN_TOKEN");
+        if admin_token
+            .as_ref()
+            .is_some_and(|token| token.len() < MIN_ADMIN_TOKEN_LEN)
+        {
+            settings.invalid(format!(
+                "ADMIN_TOKEN must be at least {MIN_ADMIN_TOKEN_LEN} characters"
+            ));
+        }
+        AppConfig {
+            database,
+            router,
+            purge,
+            telemetry: None,
+            admin_token: admin_token.map(Secret::new),
+            server: None,
+        }
+    }
+
+    /// The configuration with secrets redacted, as `/admin/config` serves it.
+    pub fn redacted(&self) -> serde_json::Value {
+        serde_json::to_value(self).expect("config serializes to JSON")
+    }
+
+    /// [`AppConfig::router`], serving `/admin/config` when `admin_token` is
+    /// set.
+    pub fn router_config(&self) -> RouterConfig {
+        RouterConfig {
+            admin: self.admin_token.clone().map(|token| AdminConfig {
+                token,
+                config: self.redacted(),
+            }),
+            ..self.router.clone()
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::{borrow::Cow, error::Error};
+
+use axum::{
+    extract::rejection::{JsonRejection, QueryRejection},
+    http::{header::WWW_AUTHENTICATE, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use shuttle_runtime::tracing;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{request_id::RequestId, validation::FieldError};
+
+/// Postgres SQLSTATE codes we map to client errors.
+const UNIQUE_VIOLATION: &str = "23505";
+const CHECK_VIOLATION: &str = "23514";
+const NOT_NULL_VIOLATION: &str = "23502";
+const STRING_DATA_RIGHT_TRUNCATION: &str = "22001";
+const CHARACTER_NOT_IN_REPERTOIRE: &str = "22021";
+
+/// Errors returned by the API handlers.
+///
+/// Database errors keep their source so it can be logged, but only the
+/// stable `code` and `message` are sent to the client.
+#[derive(Debug)]
+pub enum AppError {
+    NotFound(Cow<'static, str>),
+    BadRequest(Cow<'static, str>),
+    InvalidBody(JsonRejection),
+    Validation(Vec<FieldError>),
+    Unauthorized(&'static str),
+    Forbidden(&'static str),
+    PreconditionRequired(&'static str),
+    PreconditionFailed(&'static str),
+    TooManyRequests(&'static str),
+    RequestTimeout(&'static str),
+    GatewayTimeout(&'static str),
+    Conflict(sqlx::Error),
+    ConstraintViolation(sqlx::Error),
+    Unavailable(sqlx::Error),
+    Internal(Box<dyn Error + Send + Sync>),
+}
+
+/// JSON body sent for every error response.
+#[derive(Debug, Serialize, ToSchema)]
+pub(crate) struct ErrorBody {
+    code: &'static str,
+    #[schema(value_type = String)]
+    message: Cow<'static, str>,
+    request_id: String,
+    /// Invalid fields, on `validation_failed` only.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<FieldError>,
+}
+
+impl AppError {
+    pub fn not_found(message: impl Into<Cow<'static, str>>) -> Self {
+        AppError::NotFound(message.into())
+    }
+
+    pub fn bad_request(message: impl Into<Cow<'static, str>>) -> Self {
+        AppError::BadRequest(message.into())
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::InvalidBody(rejection) => rejection.status(),
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::PreconditionRequired(_) => StatusCode::PRECONDITION_REQUIRED,
+            AppError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+            AppError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::RequestTimeout(_) => StatusCode::REQUEST_TIMEOUT,
+            AppError::GatewayTimeout(_) => StatusCode::GATEWAY_TIMEOUT,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::ConstraintViolation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::BadRequest(_) => "bad_request",
+            AppError::InvalidBody(rejection)
+                if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE =>
+            {
+                "payload_too_large"
+            }
+            AppError::InvalidBody(_) => "invalid_body",
+            AppError::Validation(_) => "validation_failed",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::PreconditionRequired(_) => "precondition_required",
+            AppError::PreconditionFailed(_) => "precondition_failed",
+            AppError::TooManyRequests(_) => "rate_limited",
+            AppError::RequestTimeout(_) => "request_timeout",
+            AppError::GatewayTimeout(_) => "gateway_timeout",
+            AppError::Conflict(_) => "conflict",
+            AppError::ConstraintViolation(_) => "constraint_violation",
+            AppError::Unavailable(_) => "service_unavailable",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+
+    fn message(&self) -> Cow<'static, str> {
+        match self {
+            AppError::Unauthorized(message)
+            | AppError::Forbidden(message)
+            | AppError::PreconditionRequired(message)
+            | AppError::PreconditionFailed(message)
+            | AppError::TooManyRequests(message)
+            | AppError::RequestTimeout(message)
+            | AppError::GatewayTimeout(message) => Cow::Borrowed(message),
+            AppError::NotFound(message) | AppError::BadRequest(message) => message.clone(),
+            AppError::InvalidBody(rejection) => rejection.body_text().into(),
+            AppError::Validation(_) => "the request payload is invalid".into(),
+            AppError::Conflict(_) => "the request conflicts with an existing resource".into(),
+            AppError::ConstraintViolation(_) => "the request violates a data constraint".into(),
+            AppError::Unavailable(_) => "the service is temporarily unavailable".into(),
+            AppError::Internal(_) => "an internal error occurred".into(),
+        }
+    }
+
+    /// Details that must stay server-side.
+    fn internal_source(&self) -> Option<&dyn Error> {
+        match self {
+            AppError::Conflict(error)
+            | AppError::ConstraintViolation(error)
+            | AppError::Unavailable(error) => Some(error),
+            AppError::Internal(error) => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(error: sqlx::Error) -> Self {
+        match &error {
+            sqlx::Error::RowNotFound => AppError::not_found("test not found"),
+            sqlx::Error::PoolTimedOut => AppError::Unavailable(error),
+            sqlx::Error::Database(db) => match db.code().as_deref() {
+                Some(UNIQUE_VIOLATION) => AppError::Conflict(error),
+                Some(
+                    CHECK_VIOLATION
+                    | NOT_NULL_VIOLATION
+                    | STRING_DATA_RIGHT_TRUNCATION
+                    | CHARACTER_NOT_IN_REPERTOIRE,
+                ) => AppError::ConstraintViolation(error),
+                _ => AppError::Internal(Box::new(error)),
+            },
+            _ => AppError::Internal(Box::new(error)),
+        }
+    }
+}
+
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        AppError::bad_request(rejection.body_text())
+    }
+}
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        AppError::InvalidBody(rejection)
+    }
+}
+
+impl AppError {
+    /// Logs the error and builds what the client is told: the status and
+    /// the body, which is also sent in WebSocket error frames. The body's
+    /// `request_id` is that of the request being handled.
+    pub(crate) fn report(self) -> (StatusCode, ErrorBody) {
+        let status = self.status();
+        // Outside of a request, as in tests, the error gets an id of its own.
+        let request_id = RequestId::current()
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+        match self.internal_source() {
+            Some(source) if status.is_server_error() => {
+                tracing::error!(%request_id, code = self.code(), error = %source, "request failed")
+            }
+            Some(source) => {
+                tracing::warn!(%request_id, code = self.code(), error = %source, "request rejected")
+            }
+            None => tracing::debug!(%request_id, code = self.code(), "request rejected"),
+        }
+
+        let code = self.code();
+        let message = self.message();
+        let errors = match self {
+            AppError::Validation(errors) => errors,
+            _ => Vec::new(),
+        };
+        let body = ErrorBody {
+            code,
+            message,
+            request_id,
+            errors,
+        };
+        (status, body)
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, body) = self.report();
+        let mut response = (status, Json(body)).into_response();
+        if status == StatusCode::UNAUTHORIZED {
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        response
+    }
+}
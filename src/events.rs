@@ -0,0 +1,244 @@
+//! Change subscriptions, streamed as server-sent events at `GET /txt/events`
+//! and as frames of the `GET /txt/ws` WebSocket.
+//!
+//! Every write is recorded in a changelog, the `test_changes` table or its
+//! in-memory equivalent, under an increasing id. A subscription reads its
+//! owner's changes after the last id it delivered, then waits on the
+//! repository's [`ChangeFeed`] for more, so a slow subscriber falls behind
+//! in the changelog instead of buffering in memory. Reconnecting clients
+//! resume after their last event id for as long as the changelog holds it.
+
+use std::{collections::VecDeque, convert::Infallible, str::FromStr, sync::Arc};
+
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::{stream, Stream, StreamExt};
+use serde::Serialize;
+use shuttle_runtime::tracing;
+use tokio::sync::watch;
+
+use crate::{auth::Owner, error::AppError, repository::TestRepository, Test};
+
+/// Changes kept in the changelog, matching the pruning done by the trigger
+/// in `migrations/0008_create_test_changes.sql`.
+pub const CHANGELOG_CAPACITY: usize = 10_000;
+
+/// Changes read from the changelog at once.
+const BATCH_SIZE: i64 = 100;
+
+/// What happened to a row. Restoring a row from the trash creates it again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl ChangeKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Updated => "updated",
+            ChangeKind::Deleted => "deleted",
+        }
+    }
+}
+
+impl FromStr for ChangeKind {
+    type Err = String;
+
+    fn from_str(kind: &str) -> Result<Self, Self::Err> {
+        match kind {
+            "created" => Ok(ChangeKind::Created),
+            "updated" => Ok(ChangeKind::Updated),
+            "deleted" => Ok(ChangeKind::Deleted),
+            other => Err(format!("unknown change kind {other:?}")),
+        }
+    }
+}
+
+/// A changelog entry: the row as it was written.
+#[derive(Debug, Clone)]
+pub struct Change {
+    pub id: i64,
+    pub kind: ChangeKind,
+    pub test: Test,
+}
+
+/// What a subscription delivers.
+#[derive(Debug, Clone)]
+pub(crate) enum Notice {
+    Change(Change),
+    /// The changes after the requested id are no longer kept; the client
+    /// should fetch the tests again and resume after `last`.
+    Reset {
+        last: i64,
+    },
+}
+
+impl Notice {
+    fn event(self) -> Event {
+        match self {
+            Notice::Change(change) => Event::default()
+                .id(change.id.to_string())
+                .event(change.kind.as_str())
+                .json_data(&change.test)
+                .expect("a test serializes to JSON"),
+            Notice::Reset { last } => Event::default()
+                .id(last.to_string())
+                .event("reset")
+                .data("changes since Last-Event-ID are no longer available"),
+        }
+    }
+}
+
+/// Ids the changelog holds, of every owner. `first` is past `last` while
+/// it is empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChangeWindow {
+    pub first: i64,
+    pub last: i64,
+}
+
+impl ChangeWindow {
+    /// Whether every change after `id` is still in the changelog.
+    fn can_resume_after(self, id: i64) -> bool {
+        id <= self.last && id >= self.first - 1
+    }
+}
+
+/// Wakes event streams when the changelog may have grown.
+#[derive(Debug, Clone)]
+pub struct ChangeFeed {
+    sender: Arc<watch::Sender<()>>,
+}
+
+impl Default for ChangeFeed {
+    fn default() -> Self {
+        ChangeFeed {
+            sender: Arc::new(watch::channel(()).0),
+        }
+    }
+}
+
+impl ChangeFeed {
+    pub fn notify(&self) {
+        self.sender.send_replace(());
+    }
+
+    /// A receiver that sees notifications sent from now on.
+    pub fn subscribe(&self) -> watch::Receiver<()> {
+        self.sender.subscribe()
+    }
+}
+
+/// Position of a subscription in its owner's changelog.
+struct Tail<R> {
+    repo: R,
+    owner: Owner,
+    after: i64,
+    feed: watch::Receiver<()>,
+    pending: VecDeque<Notice>,
+}
+
+impl<R: TestRepository> Tail<R> {
+    /// Waits for the next notice. The subscription ends when the changelog
+    /// cannot be read, and the client resumes from its last event id.
+    async fn next(mut self) -> Option<(Notice, Self)> {
+        loop {
+            if let Some(notice) = self.pending.pop_front() {
+                return Some((notice, self));
+            }
+            match self.repo.changes(&self.owner, self.after, BATCH_SIZE).await {
+                Ok(changes) => match changes.last() {
+                    Some(last) => {
+                        self.after = last.id;
+                        self.pending.extend(changes.into_iter().map(Notice::Change));
+                    }
+                    None => self.feed.changed().await.ok()?,
+                },
+                Err(error) => {
+                    tracing::warn!(?error, "subscription failed to read changes");
+                    return None;
+                }
+            }
+        }
+    }
+}
+
+/// Subscribes `owner` to changes after `resume_after`, or after the latest
+/// one. When `resume_after` is no longer in the changelog, or was never
+/// issued, the subscription starts with a [`Notice::Reset`].
+pub(crate) async fn subscribe<R: TestRepository>(
+    repo: R,
+    owner: Owner,
+    resume_after: Option<i64>,
+) -> Result<impl Stream<Item = Notice> + Send, AppError> {
+    // Subscribe first so nothing written after the window is read is missed.
+    let feed = repo.change_feed().subscribe();
+    let window = repo.change_window().await?;
+
+    let mut pending = VecDeque::new();
+    let after = match resume_after {
+        Some(id) if window.can_resume_after(id) => id,
+        Some(_) => {
+            pending.push_back(Notice::Reset { last: window.last });
+            window.last
+        }
+        None => window.last,
+    };
+
+    let tail = Tail {
+        repo,
+        owner,
+        after,
+        feed,
+        pending,
+    };
+    Ok(stream::unfold(tail, Tail::next))
+}
+
+fn last_event_id(headers: &HeaderMap) -> Result<Option<i64>, AppError> {
+    headers
+        .get("last-event-id")
+        .map(|value| {
+            value
+                .to_str()
+                .ok()
+                .and_then(|value| value.trim().parse().ok())
+                .ok_or_else(|| AppError::bad_request("Last-Event-ID must be an event id"))
+        })
+        .transpose()
+}
+
+#[utoipa::path(
+    get,
+    path = "/txt/events",
+    tag = "txt",
+    params(
+        ("Last-Event-ID" = Option<i64>, Header,
+            description = "Id of the last event received, to replay the ones after it"),
+    ),
+    responses(
+        (status = 200, description = "`created`, `updated` and `deleted` events whose data is \
+            the test as written. A `reset` event means the changes after `Last-Event-ID` are \
+            no longer kept and the client should fetch the tests again.",
+            body = Test, content_type = "text/event-stream"),
+        (status = 400, description = "Invalid `Last-Event-ID`", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn stream_events<R: TestRepository>(
+    State(repo): State<R>,
+    owner: Owner,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let notices = subscribe(repo, owner, last_event_id(&headers)?).await?;
+    let events = notices.map(|notice| Ok(notice.event()));
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Serialize;
+use shuttle_runtime::tracing;
+use utoipa::ToSchema;
+
+use crate::repository::TestRepository;
+
+/// How long a readiness check may wait on the database.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Unavailable,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Liveness {
+    status: Status,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Check {
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<String>)]
+    error: Option<&'static str>,
+}
+
+impl Check {
+    fn from_result(name: &str, result: Result<(), &'static str>) -> Self {
+        match result {
+            Ok(()) => Check {
+                status: Status::Ok,
+                error: None,
+            },
+            Err(error) => {
+                tracing::warn!(check = name, error, "readiness check failed");
+                Check {
+                    status: Status::Unavailable,
+                    error: Some(error),
+                }
+            }
+        }
+    }
+
+    fn is_ok(&self) -> bool {
+        matches!(self.status, Status::Ok)
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Checks {
+    database: Check,
+    schema: Check,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PoolStats {
+    size: u32,
+    idle: usize,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Readiness {
+    status: Status,
+    checks: Checks,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pool: Option<PoolStats>,
+}
+
+/// Liveness probe: the process is up and serving requests.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "health",
+    responses((status = 200, description = "The process is up", body = Liveness))
+)]
+pub async fn healthz() -> Json<Liveness> {
+    Json(Liveness { status: Status::Ok })
+}
+
+/// Readiness probe: the database answers and the schema is in place.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "health",
+    responses(
+        (status = 200, description = "Ready to serve traffic", body = Readiness),
+        (status = 503, description = "A check failed", body = Readiness),
+    )
+)]
+pub async fn readyz<R: TestRepository>(State(repo): State<R>) -> (StatusCode, Json<Readiness>) {
+    let database = Check::from_result("database", ping(&repo).await);
+    let schema = if database.is_ok() {
+        Check::from_result("schema", table_exists(&repo).await)
+    } else {
+        Check::from_result("schema", Err("skipped, database is unavailable"))
+    };
+
+    let ready = database.is_ok() && schema.is_ok();
+    let readiness = Readiness {
+        status: if ready {
+            Status::Ok
+        } else {
+            Status::Unavailable
+        },
+        checks: Checks { database, schema },
+        pool: repo.pool_stats().map(|stats| PoolStats {
+            size: stats.size,
+            idle: stats.idle,
+        }),
+    };
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
+
+async fn ping<R: TestRepository>(repo: &R) -> Result<(), &'static str> {
+    match tokio::time::timeout(CHECK_TIMEOUT, repo.ping()).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(error)) => {
+            tracing::warn!(?error, "database ping failed");
+            Err("database is unreachable")
+        }
+        Err(_) => Err("database ping timed out"),
+    }
+}
+
+async fn table_exists<R: TestRepository>(repo: &R) -> Result<(), &'static str> {
+    match tokio::time::timeout(CHECK_TIMEOUT, repo.schema_ready()).await {
+        Ok(Ok(true)) => Ok(()),
+        Ok(Ok(false)) => Err("table test does not exist"),
+        Ok(Err(error)) => {
+            tracing::warn!(?error, "schema check failed");
+            Err("schema could not be inspected")
+        }
+        Err(_) => Err("schema check timed out"),
+    }
+}
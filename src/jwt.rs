@@ -0,0 +1,139 @@
+//! Verification of user JWTs, which identify the owner of `Test` rows.
+
+use std::fmt;
+
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
+
+use crate::{config::REDACTED, error::AppError};
+
+/// Keys and claims accepted for user tokens.
+#[derive(Clone, Default)]
+pub struct JwtConfig {
+    /// Shared secret for HS256 tokens.
+    pub hs256_secret: Option<String>,
+    /// PEM-encoded public key for RS256 tokens.
+    pub rs256_public_key: Option<String>,
+    /// Required `iss` claim, if set.
+    pub issuer: Option<String>,
+    /// Required `aud` claim, if set.
+    pub audience: Option<String>,
+}
+
+impl fmt::Debug for JwtConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwtConfig")
+            .field(
+                "hs256_secret",
+                &self.hs256_secret.as_ref().map(|_| REDACTED),
+            )
+            .field("rs256_public_key", &self.rs256_public_key.is_some())
+            .field("issuer", &self.issuer)
+            .field("audience", &self.audience)
+            .finish()
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Claims {
+    sub: String,
+}
+
+/// Verifies HS256/RS256 tokens against locally configured keys.
+#[derive(Clone)]
+pub struct JwtVerifier {
+    hs256: Option<DecodingKey>,
+    rs256: Option<DecodingKey>,
+    issuer: Option<String>,
+    audience: Option<String>,
+}
+
+impl fmt::Debug for JwtVerifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("JwtVerifier")
+            .field("hs256", &self.hs256.is_some())
+            .field("rs256", &self.rs256.is_some())
+            .field("issuer", &self.issuer)
+            .field("audience", &self.audience)
+            .finish()
+    }
+}
+
+/// Shows which keys are configured, never the keys themselves.
+impl Serialize for JwtVerifier {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("JwtVerifier", 4)?;
+        state.serialize_field("hs256_secret", &self.hs256.as_ref().map(|_| REDACTED))?;
+        state.serialize_field("rs256_public_key", &self.rs256.as_ref().map(|_| REDACTED))?;
+        state.serialize_field("issuer", &self.issuer)?;
+        state.serialize_field("audience", &self.audience)?;
+        state.end()
+    }
+}
+
+impl JwtVerifier {
+    /// Builds a verifier, or `None` when no key is configured.
+    pub fn new(config: &JwtConfig) -> Result<Option<Self>, String> {
+        let hs256 = config
+            .hs256_secret
+            .as_deref()
+            .map(|secret| DecodingKey::from_secret(secret.as_bytes()));
+        let rs256 = config
+            .rs256_public_key
+            .as_deref()
+            .map(|pem| DecodingKey::from_rsa_pem(pem.as_bytes()))
+            .transpose()
+            .map_err(|error| format!("invalid RS256 public key: {error}"))?;
+
+        if hs256.is_none() && rs256.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(JwtVerifier {
+            hs256,
+            rs256,
+            issuer: config.issuer.clone(),
+            audience: config.audience.clone(),
+        }))
+    }
+
+    /// Whether a bearer token should be treated as a JWT rather than an API
+    /// key.
+    pub fn looks_like_jwt(token: &str) -> bool {
+        token.split('.').count() == 3
+    }
+
+    /// Validates the signature, `exp` and the configured `iss`/`aud`, and
+    /// returns the subject.
+    pub fn verify(&self, token: &str) -> Result<String, AppError> {
+        let invalid = || AppError::Unauthorized("the bearer token is invalid or expired");
+
+        let header = jsonwebtoken::decode_header(token).map_err(|_| invalid())?;
+        let key = match header.alg {
+            Algorithm::HS256 => self.hs256.as_ref(),
+            Algorithm::RS256 => self.rs256.as_ref(),
+            _ => None,
+        }
+        .ok_or(AppError::Unauthorized(
+            "the bearer token uses an unsupported algorithm",
+        ))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_required_spec_claims(&["exp", "sub"]);
+        match &self.issuer {
+            Some(issuer) => validation.set_issuer(&[issuer]),
+            None => validation.iss = None,
+        }
+        match &self.audience {
+            Some(audience) => validation.set_audience(&[audience]),
+            None => validation.validate_aud = false,
+        }
+
+        let claims = jsonwebtoken::decode::<Claims>(token, key, &validation)
+            .map_err(|_| invalid())?
+            .claims;
+        if claims.sub.is_empty() {
+            return Err(invalid());
+        }
+        Ok(claims.sub)
+    }
+}
@@ -0,0 +1,269 @@
+//! The HTTP middleware around every route: CORS, response compression,
+//! request decompression, a per-request timeout and a body size limit.
+
+use std::{
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use axum::{
+    body::{Body, Bytes, HttpBody},
+    error_handling::HandleErrorLayer,
+    extract::{DefaultBodyLimit, State},
+    http::{
+        header::{
+            AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE, ETAG, IF_MATCH, IF_MODIFIED_SINCE,
+            IF_NONE_MATCH, LAST_MODIFIED,
+        },
+        HeaderName, HeaderValue, Method, Request,
+    },
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    BoxError, Router,
+};
+use futures_util::Stream;
+use serde::{Serialize, Serializer};
+use tower::ServiceBuilder;
+use tower_http::{
+    compression::CompressionLayer,
+    cors::{AllowOrigin, CorsLayer},
+    decompression::{DecompressionBody, RequestDecompressionLayer},
+};
+
+use crate::{
+    config::{self, Settings},
+    error::AppError,
+    request_id::X_REQUEST_ID,
+};
+
+/// How long browsers may cache a CORS preflight.
+const CORS_MAX_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Request headers browsers may send cross-origin.
+const CORS_ALLOW_HEADERS: [HeaderName; 8] = [
+    AUTHORIZATION,
+    CONTENT_TYPE,
+    CONTENT_ENCODING,
+    IF_MATCH,
+    IF_NONE_MATCH,
+    IF_MODIFIED_SINCE,
+    HeaderName::from_static("last-event-id"),
+    X_REQUEST_ID,
+];
+
+/// Response headers cross-origin scripts may read.
+const CORS_EXPOSE_HEADERS: [HeaderName; 7] = [
+    ETAG,
+    LAST_MODIFIED,
+    X_REQUEST_ID,
+    HeaderName::from_static("ratelimit-limit"),
+    HeaderName::from_static("ratelimit-remaining"),
+    HeaderName::from_static("ratelimit-reset"),
+    HeaderName::from_static("retry-after"),
+];
+
+/// Origins allowed to call the API from a browser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CorsOrigins {
+    Any,
+    List(Vec<HeaderValue>),
+}
+
+impl Serialize for CorsOrigins {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            CorsOrigins::Any => serializer.serialize_str("*"),
+            CorsOrigins::List(origins) => serializer.collect_seq(
+                origins
+                    .iter()
+                    .map(|origin| String::from_utf8_lossy(origin.as_bytes())),
+            ),
+        }
+    }
+}
+
+impl FromStr for CorsOrigins {
+    type Err = String;
+
+    fn from_str(origins: &str) -> Result<Self, Self::Err> {
+        if origins.trim() == "*" {
+            return Ok(CorsOrigins::Any);
+        }
+        origins
+            .split(',')
+            .map(str::trim)
+            .filter(|origin| !origin.is_empty())
+            .map(|origin| {
+                let valid = origin.starts_with("http://") || origin.starts_with("https://");
+                valid
+                    .then(|| HeaderValue::from_str(origin.trim_end_matches('/')).ok())
+                    .flatten()
+                    .ok_or_else(|| format!("{origin:?} is not an http(s) origin"))
+            })
+            .collect::<Result<_, _>>()
+            .map(CorsOrigins::List)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LayerConfig {
+    /// Answer CORS requests from these origins, or send no CORS headers.
+    pub cors: Option<CorsOrigins>,
+    /// Compress responses with gzip, brotli or zstd when accepted.
+    pub compression: bool,
+    /// Accept request bodies sent with a gzip, brotli or zstd
+    /// `Content-Encoding`.
+    pub decompression: bool,
+    /// Time a request may take until its response starts, or no limit.
+    #[serde(rename = "timeout_secs", serialize_with = "config::opt_secs")]
+    pub timeout: Option<Duration>,
+    /// Largest request body accepted, after decompression.
+    pub max_body_bytes: usize,
+}
+
+impl Default for LayerConfig {
+    fn default() -> Self {
+        LayerConfig {
+            cors: None,
+            compression: true,
+            decompression: true,
+            timeout: Some(Duration::from_secs(30)),
+            max_body_bytes: 2 * 1024 * 1024,
+        }
+    }
+}
+
+impl LayerConfig {
+    /// Reads `CORS_ALLOWED_ORIGINS`, `COMPRESSION_ENABLED`,
+    /// `DECOMPRESSION_ENABLED`, `REQUEST_TIMEOUT_SECS` (`0` for no limit)
+    /// and `MAX_BODY_BYTES`. Unset settings keep their defaults.
+    pub(crate) fn read(settings: &mut Settings) -> Self {
+        let defaults = LayerConfig::default();
+        let cors = settings
+            .get("CORS_ALLOWED_ORIGINS")
+            .filter(|value| !value.trim().is_empty())
+            .and_then(|origins| match origins.parse() {
+                Ok(origins) => Some(origins),
+                Err(error) => {
+                    settings.invalid(format!("CORS_ALLOWED_ORIGINS: {error}"));
+                    None
+                }
+            });
+        let default_secs = defaults.timeout.map_or(0, |timeout| timeout.as_secs());
+        let timeout = match settings.parse("REQUEST_TIMEOUT_SECS", default_secs) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        };
+        let max_body_bytes = settings.parse("MAX_BODY_BYTES", defaults.max_body_bytes);
+        settings.check(max_body_bytes > 0, "MAX_BODY_BYTES must be positive");
+        LayerConfig {
+            cors,
+            compression: settings.parse("COMPRESSION_ENABLED", defaults.compression),
+            decompression: settings.parse("DECOMPRESSION_ENABLED", defaults.decompression),
+            timeout,
+            max_body_bytes,
+        }
+    }
+}
+
+fn cors(origins: &CorsOrigins) -> CorsLayer {
+    let allow_origin = match origins {
+        CorsOrigins::Any => AllowOrigin::any(),
+        CorsOrigins::List(origins) => AllowOrigin::list(origins.iter().cloned()),
+    };
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::PATCH,
+            Method::DELETE,
+        ])
+        .allow_headers(CORS_ALLOW_HEADERS)
+        .expose_headers(CORS_EXPOSE_HEADERS)
+        .max_age(CORS_MAX_AGE)
+}
+
+/// Wraps `router`, whose routes take decompressed bodies, in the layers
+/// `config` enables.
+///
+/// Decompression changes the body type, so it is always layered and turned
+/// off by accepting no encodings. Bodies sent with one then get a `415`.
+pub(crate) fn apply<S>(
+    router: Router<S, DecompressionBody<Body>>,
+    config: &LayerConfig,
+) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    let decompression = RequestDecompressionLayer::new()
+        .gzip(config.decompression)
+        .br(config.decompression)
+        .zstd(config.decompression);
+    let mut router = router
+        .layer(DefaultBodyLimit::max(config.max_body_bytes))
+        .layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(|error: BoxError| async move {
+                    AppError::Internal(error).into_response()
+                }))
+                .layer(decompression),
+        );
+    if let Some(limit) = config.timeout {
+        router = router.layer(middleware::from_fn_with_state(limit, timeout));
+    }
+    if config.compression {
+        router = router.layer(CompressionLayer::new());
+    }
+    if let Some(origins) = &config.cors {
+        router = router.layer(cors(origins));
+    }
+    router
+}
+
+/// A request body that tracks whether the server is waiting for the client
+/// to send more of it.
+struct Tracked {
+    body: Body,
+    waiting: Arc<AtomicBool>,
+}
+
+impl Stream for Tracked {
+    type Item = Result<Bytes, axum::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = Pin::new(&mut self.body).poll_data(cx);
+        self.waiting.store(poll.is_pending(), Ordering::Relaxed);
+        poll.map(|chunk| chunk.map(|chunk| chunk.map_err(axum::Error::new)))
+    }
+}
+
+/// Fails requests whose response has not started within `limit`: with a
+/// `408` when the client was still sending the body, and with a `504` when
+/// the request was being handled, such as by a slow query.
+///
+/// Streaming responses only need to start in time.
+async fn timeout(State(limit): State<Duration>, req: Request<Body>, next: Next<Body>) -> Response {
+    let waiting = Arc::new(AtomicBool::new(false));
+    let req = if req.body().is_end_stream() {
+        req
+    } else {
+        let waiting = waiting.clone();
+        req.map(|body| Body::wrap_stream(Tracked { body, waiting }))
+    };
+
+    match tokio::time::timeout(limit, next.run(req)).await {
+        Ok(response) => response,
+        Err(_) if waiting.load(Ordering::Relaxed) => {
+            AppError::RequestTimeout("the request body was not received in time").into_response()
+        }
+        Err(_) => AppError::GatewayTimeout("the request took too long to process").into_response(),
+    }
+}
@@ -0,0 +1,453 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{rejection::QueryRejection, Path, Query, State},
+    http::{
+        header::{ETAG, IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+        HeaderMap, HeaderName, HeaderValue, StatusCode,
+    },
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    admin::AdminConfig,
+    auth::{AuthConfig, AuthState, Owner},
+    config::Settings,
+    error::AppError,
+    layers::LayerConfig,
+    pagination::{Cursor, Order, Page},
+    rate_limit::{RateLimitConfig, RateLimiter},
+    repository::{ApiKeyRepository, ListQuery, TestRepository, UpdateOutcome},
+    validation::{TestInput, Valid},
+};
+
+pub mod admin;
+pub mod auth;
+mod bulk;
+pub mod config;
+pub mod error;
+pub mod events;
+mod health;
+pub mod jwt;
+pub mod layers;
+mod metrics;
+pub mod migrate;
+pub mod openapi;
+mod pagination;
+pub mod rate_limit;
+pub mod repository;
+pub mod request_id;
+mod search;
+pub mod telemetry;
+pub mod trash;
+mod validation;
+mod ws;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow, ToSchema)]
+pub struct Test {
+    pub id: i32,
+    pub txt: String,
+    pub version: i32,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// `strftime` format of an HTTP date (IMF-fixdate).
+const HTTP_DATE: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+impl Test {
+    /// Strong entity tag derived from the row version.
+    fn etag(&self) -> HeaderValue {
+        HeaderValue::from_str(&format!("\"{}\"", self.version)).expect("valid etag")
+    }
+
+    /// `updated_at` as an HTTP date, truncated to whole seconds.
+    fn last_modified(&self) -> HeaderValue {
+        HeaderValue::from_str(&self.updated_at.format(HTTP_DATE).to_string()).expect("valid date")
+    }
+
+    fn validators(&self) -> [(HeaderName, HeaderValue); 2] {
+        [(ETAG, self.etag()), (LAST_MODIFIED, self.last_modified())]
+    }
+
+    /// Whether the copy the client validates with `If-None-Match` or, when
+    /// that is absent, `If-Modified-Since` is still current.
+    fn is_fresh_for(&self, headers: &HeaderMap) -> bool {
+        if let Some(value) = headers.get(IF_NONE_MATCH) {
+            let current = self.version.to_string();
+            // If-None-Match uses the weak comparison, so W/ tags match too.
+            return value.to_str().is_ok_and(|value| {
+                value.split(',').map(str::trim).any(|tag| {
+                    tag == "*"
+                        || tag
+                            .trim_start_matches("W/")
+                            .strip_prefix('"')
+                            .and_then(|tag| tag.strip_suffix('"'))
+                            == Some(current.as_str())
+                })
+            });
+        }
+        headers
+            .get(IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+            .is_some_and(|since| self.updated_at.timestamp() <= since.timestamp())
+    }
+}
+
+/// A `Test` response carrying its `ETag` and `Last-Modified`.
+type Tagged = ([(HeaderName, HeaderValue); 2], Json<Test>);
+
+fn tagged(test: Test) -> Tagged {
+    (test.validators(), Json(test))
+}
+
+/// Versions a client expects to be current, as sent in `If-Match`.
+enum Precondition {
+    Any,
+    Versions(Vec<i32>),
+}
+
+impl Precondition {
+    fn from_headers(headers: &HeaderMap) -> Result<Self, AppError> {
+        let value = headers
+            .get(IF_MATCH)
+            .ok_or(AppError::PreconditionRequired(
+                "updates require an If-Match header",
+            ))?
+            .to_str()
+            .map_err(|_| AppError::bad_request("If-Match is not a valid header value"))?;
+
+        if value.trim() == "*" {
+            return Ok(Precondition::Any);
+        }
+
+        // Weak tags never match under the strong comparison If-Match requires.
+        let versions = value
+            .split(',')
+            .filter_map(|tag| tag.trim().strip_prefix('"')?.strip_suffix('"'))
+            .filter_map(|tag| tag.parse().ok())
+            .collect();
+        Ok(Precondition::Versions(versions))
+    }
+
+    fn versions(&self) -> Option<&[i32]> {
+        match self {
+            Precondition::Any => None,
+            Precondition::Versions(versions) => Some(versions),
+        }
+    }
+}
+
+fn not_found() -> AppError {
+    AppError::not_found("test not found")
+}
+
+#[utoipa::path(
+    get,
+    path = "/",
+    responses((status = 200, description = "Greeting", body = String, content_type = "text/plain"))
+)]
+async fn root() -> &'static str {
+    "Hello Axum! Shuttle rocks!"
+}
+
+#[utoipa::path(
+    post,
+    path = "/txt",
+    tag = "txt",
+    request_body(content = String, description = "The `txt`, trimmed before it is stored"),
+    responses(
+        (status = 200, description = "The created test", body = Test),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "The API key is read-only", body = ErrorBody),
+        (status = 422, description = "`txt` is empty or too long", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+async fn create_test<R: TestRepository>(
+    State(repo): State<R>,
+    owner: Owner,
+    Valid(input): Valid<TestInput>,
+) -> Result<Json<Test>, AppError> {
+    let test = create(&repo, &owner, input).await?;
+    Ok(Json(test))
+}
+
+/// Creates a row from a validated input, for `POST /txt` and WebSocket
+/// commands.
+async fn create<R: TestRepository>(
+    repo: &R,
+    owner: &Owner,
+    input: TestInput,
+) -> Result<Test, AppError> {
+    repo.create(owner, &input.txt).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/txt/{id}",
+    tag = "txt",
+    params(
+        ("id" = i32, Path, description = "Test id"),
+        ("If-None-Match" = Option<String>, Header, description = "`ETag` of a cached copy"),
+        ("If-Modified-Since" = Option<String>, Header,
+            description = "`Last-Modified` of a cached copy, ignored with `If-None-Match`"),
+    ),
+    responses(
+        (status = 200, description = "The test", body = Test,
+            headers(
+                ("ETag" = String, description = "Current version, for `If-Match`"),
+                ("Last-Modified" = String, description = "`updated_at` as an HTTP date"),
+            )),
+        (status = 304, description = "The cached copy is current",
+            headers(("ETag" = String), ("Last-Modified" = String))),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 404, description = "No such test", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+async fn get_test<R: TestRepository>(
+    State(repo): State<R>,
+    owner: Owner,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
+    let test = repo.get(&owner, id).await?.ok_or_else(not_found)?;
+    if test.is_fresh_for(&headers) {
+        return Ok((StatusCode::NOT_MODIFIED, test.validators()).into_response());
+    }
+    Ok(tagged(test).into_response())
+}
+
+#[utoipa::path(
+    put,
+    path = "/txt/{id}",
+    tag = "txt",
+    params(
+        ("id" = i32, Path, description = "Test id"),
+        ("If-Match" = String, Header, description = "`ETag` the update is based on, or `*`"),
+    ),
+    request_body(content = String, description = "The new `txt`, trimmed before it is stored"),
+    responses(
+        (status = 200, description = "The updated test", body = Test,
+            headers(
+                ("ETag" = String, description = "New version"),
+                ("Last-Modified" = String, description = "`updated_at` as an HTTP date"),
+            )),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "The API key is read-only", body = ErrorBody),
+        (status = 404, description = "No such test", body = ErrorBody),
+        (status = 412, description = "The test has been modified since it was read", body = ErrorBody),
+        (status = 422, description = "`txt` is empty or too long", body = ErrorBody),
+        (status = 428, description = "`If-Match` is missing", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+async fn update_test<R: TestRepository>(
+    State(repo): State<R>,
+    owner: Owner,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+    Valid(input): Valid<TestInput>,
+) -> Result<Tagged, AppError> {
+    let precondition = Precondition::from_headers(&headers)?;
+    match repo
+        .update(&owner, id, &input.txt, precondition.versions())
+        .await?
+    {
+        UpdateOutcome::Updated(test) => Ok(tagged(test)),
+        UpdateOutcome::NotFound => Err(not_found()),
+        UpdateOutcome::VersionMismatch => Err(AppError::PreconditionFailed(
+            "test has been modified since it was read",
+        )),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/txt/{id}",
+    tag = "txt",
+    params(("id" = i32, Path, description = "Test id")),
+    responses(
+        (status = 200, description = "The test, now in the trash", body = Test),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "The API key is read-only", body = ErrorBody),
+        (status = 404, description = "No such test", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+async fn delete_test<R: TestRepository>(
+    State(repo): State<R>,
+    owner: Owner,
+    Path(id): Path<i32>,
+) -> Result<Json<Test>, AppError> {
+    let test = delete(&repo, &owner, id).await?;
+    Ok(Json(test))
+}
+
+/// Moves a row to the trash, for `DELETE /txt/:id` and WebSocket commands.
+async fn delete<R: TestRepository>(repo: &R, owner: &Owner, id: i32) -> Result<Test, AppError> {
+    repo.delete(owner, id).await?.ok_or_else(not_found)
+}
+
+/// Query parameters accepted by `GET /txt`.
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(crate) struct ListParams {
+    /// Page size, 1 to 100; defaults to 50.
+    limit: Option<i64>,
+    /// `next_cursor` of the previous page.
+    cursor: Option<String>,
+    #[serde(default)]
+    #[param(inline)]
+    order: Order,
+    /// Case-insensitive substring filter on `txt`.
+    q: Option<String>,
+    /// Only rows updated after this RFC 3339 instant.
+    since: Option<DateTime<Utc>>,
+}
+
+impl ListParams {
+    /// The validated page size, and a query that fetches one extra row to
+    /// detect the next page.
+    fn into_query(self) -> Result<(ListQuery, i64), AppError> {
+        let limit = pagination::limit(self.limit)?;
+        let query = ListQuery {
+            cursor: self.cursor.as_deref().map(Cursor::decode).transpose()?,
+            order: self.order,
+            q: self.q.filter(|q| !q.is_empty()),
+            since: self.since,
+            limit: limit + 1,
+        };
+        Ok((query, limit))
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/txt",
+    tag = "txt",
+    params(ListParams),
+    responses(
+        (status = 200, description = "A page of tests ordered by id, without trashed ones", body = TestPage),
+        (status = 400, description = "Invalid `limit` or `cursor`", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+async fn list_tests<R: TestRepository>(
+    State(repo): State<R>,
+    owner: Owner,
+    params: Result<Query<ListParams>, QueryRejection>,
+) -> Result<Json<Page<Test>>, AppError> {
+    let Query(params) = params?;
+    let (query, limit) = params.into_query()?;
+    let tests = repo.list(&owner, &query).await?;
+    Ok(Json(Page::from_overfetch(tests, limit, |test| test.id)))
+}
+
+/// Settings for the routes and middleware built by [`router`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RouterConfig {
+    pub auth: AuthConfig,
+    /// Serve a Redoc page for `/openapi.json` at `/docs`.
+    pub docs: bool,
+    /// Per-client request budgets for the `/txt` routes, or no limit.
+    pub rate_limit: Option<RateLimitConfig>,
+    /// CORS, compression, timeout and body size settings for every route.
+    pub layers: LayerConfig,
+    /// Serve `/admin/config`; see
+    /// [`config::AppConfig::router_config`].
+    #[serde(skip)]
+    pub admin: Option<AdminConfig>,
+}
+
+impl RouterConfig {
+    /// Reads `API_DOCS` and the [`AuthConfig`], [`RateLimitConfig`] and
+    /// [`LayerConfig`] settings.
+    pub(crate) fn read(settings: &mut Settings) -> Self {
+        RouterConfig {
+            auth: AuthConfig::read(settings),
+            docs: settings.parse("API_DOCS", false),
+            rate_limit: RateLimitConfig::read(settings),
+            layers: LayerConfig::read(settings),
+            admin: None,
+        }
+    }
+}
+
+/// Builds the API router on top of a repository, such as a
+/// [`repository::PgRepository`] over a migrated pool.
+///
+/// The `/txt` routes require an API key; see [`auth::authenticate`]. Every
+/// response carries an `X-Request-Id`; see [`request_id`]. New routes need
+/// a `#[utoipa::path]` listed in [`openapi`] too, and their path in
+/// `ROUTED_PATHS` in `tests/openapi.rs`.
+pub async fn router<R>(repo: R, config: RouterConfig) -> Router
+where
+    R: TestRepository + ApiKeyRepository,
+{
+    let auth = AuthState::new(repo.clone(), config.auth);
+    let mut txt = Router::new()
+        .route("/txt", get(list_tests::<R>).post(create_test::<R>))
+        .route("/txt/search", get(search::search_tests::<R>))
+        .route("/txt/events", get(events::stream_events::<R>))
+        .route("/txt/ws", get(ws::connect::<R>))
+        .route("/txt/trash", get(trash::list_trash::<R>))
+        .route("/txt/:id/restore", post(trash::restore_test::<R>))
+        .route(
+            "/txt/bulk",
+            post(bulk::create_tests::<R>).delete(bulk::delete_tests::<R>),
+        )
+        .route(
+            "/txt/:id",
+            get(get_test::<R>)
+                .put(update_test::<R>)
+                .patch(update_test::<R>)
+                .delete(delete_test::<R>),
+        );
+    let limiter = config.rate_limit.map(RateLimiter::new);
+    if let Some(limiter) = &limiter {
+        txt = txt.route_layer(middleware::from_fn_with_state(
+            limiter.clone(),
+            rate_limit::limit,
+        ));
+    }
+    txt = txt.route_layer(middleware::from_fn_with_state(
+        auth,
+        auth::authenticate::<R, _>,
+    ));
+    if let Some(limiter) = limiter {
+        txt = txt.route_layer(middleware::from_fn_with_state(
+            limiter,
+            rate_limit::limit_failed_auth,
+        ));
+    }
+
+    let mut router = Router::new()
+        .route("/", get(root))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz::<R>))
+        .route("/openapi.json", get(openapi::openapi_json))
+        .route("/metrics", get(metrics::render::<R>))
+        .route(
+            "/admin/config",
+            get(admin::config).with_state(config.admin.map(Arc::new)),
+        )
+        .merge(txt);
+    if config.docs {
+        router = router.route("/docs", get(openapi::docs));
+    }
+    layers::apply(router, &config.layers)
+        .layer(middleware::from_fn(metrics::track))
+        .layer(middleware::from_fn(request_id::trace))
+        .with_state(repo)
+}
@@ -1,81 +1,34 @@
-use std::error::Error;
-
-use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    routing::{delete, get},
-    Json, Router,
-};
-use serde::{Deserialize, Serialize};
+use app::{config::AppConfig, migrate, repository::PgRepository, router, trash};
 use shuttle_runtime::{tracing, CustomError};
-use sqlx::{Executor, FromRow, PgPool};
-
-#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
-struct Test {
-    id: i32,
-    txt: String,
-}
-
-type AppError = (StatusCode, String);
-
-fn err<E>(status_code: StatusCode) -> impl FnOnce(E) -> AppError
-where
-    E: Error,
-{
-    move |error: E| (status_code, error.to_string())
-}
-
-async fn root() -> &'static str {
-    "Hello Axum! Shuttle rocks!"
-}
-
-async fn create_test(
-    State(db): State<PgPool>,
-    Json(txt): Json<String>,
-) -> Result<Json<Test>, AppError> {
-    let test = sqlx::query_as::<_, Test>("INSERT INTO test (txt) VALUES ($1) RETURNING id, txt")
-        .bind(txt.as_str())
-        .fetch_one(&db)
-        .await
-        .map_err(err(StatusCode::INTERNAL_SERVER_ERROR))?;
-
-    Ok(Json(test))
-}
-
-async fn delete_test(
-    State(db): State<PgPool>,
-    Path(id): Path<i32>,
-) -> Result<Json<Test>, AppError> {
-    let test = sqlx::query_as::<_, Test>("DELETE FROM test WHERE id = $1 RETURNING id, txt")
-        .bind(id)
-        .fetch_one(&db)
-        .await
-        .map_err(err(StatusCode::INTERNAL_SERVER_ERROR))?;
-    Ok(Json(test))
-}
-
-async fn list_tests(State(db): State<PgPool>) -> Result<Json<Vec<Test>>, AppError> {
-    let tests = sqlx::query_as::<_, Test>("SELECT * FROM test")
-        .fetch_all(&db)
-        .await
-        .map_err(err(StatusCode::INTERNAL_SERVER_ERROR))?;
-    Ok(Json(tests))
-}
+use shuttle_secrets::SecretStore;
+use sqlx::PgPool;
 
 #[shuttle_runtime::main]
-async fn axum(#[shuttle_shared_db::Postgres] pool: PgPool) -> shuttle_axum::ShuttleAxum {
-    pool.execute(include_str!("../db/schema.sql"))
+async fn axum(
+    #[shuttle_shared_db::Postgres] shared: PgPool,
+    #[shuttle_secrets::Secrets] secrets: SecretStore,
+) -> shuttle_axum::ShuttleAxum {
+    let config = AppConfig::from_lookup(|key| secrets.get(key)).map_err(CustomError::msg)?;
+    if config.router.rate_limit.is_some() {
+        // Shuttle does not pass the peer address to the router.
+        tracing::warn!(
+            "anonymous clients are rate limited by X-Forwarded-For only, \
+             and not at all without it"
+        );
+    }
+    // Shuttle's pool has a fixed size, so connect again with the configured one.
+    let pool = config
+        .database
+        .connect(shared.connect_options().clone())
         .await
         .map_err(CustomError::new)?;
-    let router = router(pool).await;
+    shared.close().await;
+
+    migrate::run(&pool).await?;
+    let repo = PgRepository::new(pool);
+    repo.spawn_listener().await.map_err(CustomError::new)?;
+    trash::spawn_purge(repo.clone(), config.purge);
+    let router = router(repo, config.router_config()).await;
     tracing::info!("Starting axum server");
     Ok(router.into())
 }
-
-async fn router(pool: PgPool) -> Router {
-    Router::new()
-        .route("/", get(root))
-        .route("/txt", get(list_tests).post(create_test))
-        .route("/txt/:id", delete(delete_test))
-        .with_state(pool)
-}
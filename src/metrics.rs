@@ -0,0 +1,159 @@
+//! Prometheus metrics, served in the text format at `/metrics`.
+//!
+//! Like the process they describe, metrics are process-wide: every router
+//! built in a process records into the same registry.
+
+use std::{future::Future, sync::OnceLock, time::Instant};
+
+use axum::{
+    extract::{MatchedPath, State},
+    http::{header::CONTENT_TYPE, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use prometheus::{
+    core::Collector, exponential_buckets, proto::MetricFamily, Encoder, HistogramOpts,
+    HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder, TEXT_FORMAT,
+};
+
+use crate::repository::{PoolStats, TestRepository};
+
+/// Route label of requests that matched no route, so unknown paths do not
+/// each get their own series or span name.
+pub(crate) const UNMATCHED: &str = "unmatched";
+
+struct Metrics {
+    registry: Registry,
+    requests: IntCounterVec,
+    request_duration: HistogramVec,
+    query_duration: HistogramVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let requests = IntCounterVec::new(
+            Opts::new(
+                "http_requests_total",
+                "HTTP requests by matched route and status class",
+            ),
+            &["method", "route", "status"],
+        )
+        .expect("valid metric");
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time to respond to HTTP requests, by matched route",
+            ),
+            &["method", "route"],
+        )
+        .expect("valid metric");
+        let query_duration = HistogramVec::new(
+            HistogramOpts::new("db_query_duration_seconds", "Time to run SQL queries")
+                .buckets(exponential_buckets(0.0005, 2.0, 14).expect("valid buckets")),
+            &["query"],
+        )
+        .expect("valid metric");
+
+        let registry = Registry::new();
+        registry
+            .register(Box::new(requests.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(request_duration.clone()))
+            .expect("metric registered once");
+        registry
+            .register(Box::new(query_duration.clone()))
+            .expect("metric registered once");
+        Metrics {
+            registry,
+            requests,
+            request_duration,
+            query_duration,
+        }
+    }
+}
+
+fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::new)
+}
+
+fn status_class(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        100..=199 => "1xx",
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        _ => "5xx",
+    }
+}
+
+/// Counts and times every request under its matched route, such as
+/// `/txt/:id`. Layered over each route, outside authentication and rate
+/// limiting so their rejections are counted too.
+pub async fn track<B>(matched: Option<MatchedPath>, req: Request<B>, next: Next<B>) -> Response {
+    let route = matched.as_ref().map_or(UNMATCHED, MatchedPath::as_str);
+    let method = req.method().clone();
+    let started = Instant::now();
+
+    let response = next.run(req).await;
+
+    let metrics = metrics();
+    metrics
+        .request_duration
+        .with_label_values(&[method.as_str(), route])
+        .observe(started.elapsed().as_secs_f64());
+    metrics
+        .requests
+        .with_label_values(&[method.as_str(), route, status_class(response.status())])
+        .inc();
+    response
+}
+
+/// Awaits a query, recording how long it took under `query`.
+pub(crate) async fn time_query<T>(query: &'static str, future: impl Future<Output = T>) -> T {
+    let timer = metrics()
+        .query_duration
+        .with_label_values(&[query])
+        .start_timer();
+    let output = future.await;
+    timer.observe_duration();
+    output
+}
+
+/// Gauges of the repository's connection pool, read when scraped.
+fn pool_families(stats: PoolStats) -> Vec<MetricFamily> {
+    let size =
+        IntGauge::new("db_pool_connections", "Open database connections").expect("valid metric");
+    size.set(stats.size.into());
+    let idle = IntGauge::new("db_pool_idle_connections", "Idle database connections")
+        .expect("valid metric");
+    idle.set(stats.idle as i64);
+
+    let mut families = size.collect();
+    families.extend(idle.collect());
+    families
+}
+
+/// Metrics in the Prometheus text format.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "metrics",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain"),
+        (status = 500, description = "The metrics could not be encoded", body = String, content_type = "text/plain"),
+    )
+)]
+pub async fn render<R: TestRepository>(State(repo): State<R>) -> Response {
+    let mut families = metrics().registry.gather();
+    if let Some(stats) = repo.pool_stats() {
+        families.extend(pool_families(stats));
+    }
+
+    let mut body = Vec::new();
+    if let Err(error) = TextEncoder::new().encode(&families, &mut body) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, error.to_string()).into_response();
+    }
+    ([(CONTENT_TYPE, TEXT_FORMAT)], body).into_response()
+}
@@ -0,0 +1,28 @@
+use shuttle_runtime::{tracing, CustomError};
+use sqlx::{migrate::MigrateError, PgPool};
+
+/// Applies the pending migrations embedded from `migrations/`.
+///
+/// Applied migrations are recorded with their checksum in `_sqlx_migrations`,
+/// so a migration that was edited or removed after being applied is reported
+/// as drift instead of being silently re-run or skipped.
+pub async fn run(pool: &PgPool) -> Result<(), CustomError> {
+    let migrator = sqlx::migrate!();
+    migrator.run(pool).await.map_err(|error| match error {
+        MigrateError::VersionMismatch(version) => CustomError::msg(format!(
+            "migration history has drifted: migration {version} was applied with a different \
+             checksum than its file in migrations/; restore the original file and add a new \
+             migration instead"
+        )),
+        MigrateError::VersionMissing(version) => CustomError::msg(format!(
+            "migration history has drifted: migration {version} was applied but no longer \
+             exists in migrations/"
+        )),
+        error => CustomError::new(error).context("failed to apply database migrations"),
+    })?;
+
+    if let Some(latest) = migrator.iter().last() {
+        tracing::info!(version = latest.version, "database schema is up to date");
+    }
+    Ok(())
+}
@@ -0,0 +1,177 @@
+//! OpenAPI 3 description of the API, generated from the handlers and the
+//! types they exchange.
+
+use axum::{response::Html, Json};
+use utoipa::{
+    openapi::{
+        header::HeaderBuilder,
+        security::{HttpAuthScheme, HttpBuilder, SecurityScheme},
+        Content, ObjectBuilder, OpenApi as Spec, PathItemType, Ref, ResponseBuilder, SchemaType,
+    },
+    Modify, OpenApi,
+};
+
+use crate::{
+    admin,
+    bulk::{self, BulkMode, BulkResponse, ItemOutcome},
+    error::ErrorBody,
+    events,
+    health::{self, Check, Checks, Liveness, PoolStats, Readiness, Status},
+    metrics,
+    pagination::{Order, TestPage, TrashPage},
+    search::{self, SearchHit},
+    trash::{self, Trashed},
+    validation::FieldError,
+    ws, Test,
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "shuttle-axum"),
+    paths(
+        crate::root,
+        health::healthz,
+        health::readyz,
+        crate::list_tests,
+        crate::create_test,
+        search::search_tests,
+        events::stream_events,
+        ws::connect,
+        trash::list_trash,
+        bulk::create_tests,
+        bulk::delete_tests,
+        crate::get_test,
+        crate::update_test,
+        crate::delete_test,
+        trash::restore_test,
+        admin::config,
+        metrics::render,
+    ),
+    components(schemas(
+        Test,
+        TestPage,
+        TrashPage,
+        Trashed,
+        Order,
+        SearchHit,
+        BulkMode,
+        BulkResponse,
+        ItemOutcome,
+        ErrorBody,
+        FieldError,
+        Liveness,
+        Readiness,
+        Checks,
+        Check,
+        PoolStats,
+        Status,
+    )),
+    modifiers(&BearerAuth, &PatchAsPut, &RateLimited),
+    tags(
+        (name = "txt", description = "Stored texts"),
+        (name = "health", description = "Probes for orchestrators"),
+        (name = "admin", description = "Operator endpoints, behind `ADMIN_TOKEN`"),
+        (name = "metrics", description = "Prometheus scrape endpoint"),
+    )
+)]
+struct ApiDoc;
+
+/// Declares the `bearer` scheme the `/txt` operations refer to. It accepts
+/// API keys and, when configured, user JWTs.
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut Spec) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+    }
+}
+
+/// `PATCH /txt/{id}` is routed to the same handler as `PUT`.
+struct PatchAsPut;
+
+impl Modify for PatchAsPut {
+    fn modify(&self, openapi: &mut Spec) {
+        if let Some(item) = openapi.paths.paths.get_mut("/txt/{id}") {
+            if let Some(put) = item.operations.get(&PathItemType::Put) {
+                let mut patch = put.clone();
+                patch.operation_id = patch.operation_id.map(|id| format!("{id}_patch"));
+                item.operations.insert(PathItemType::Patch, patch);
+            }
+        }
+    }
+}
+
+/// Every `/txt` operation may be rejected by [`crate::rate_limit::limit`].
+struct RateLimited;
+
+impl Modify for RateLimited {
+    fn modify(&self, openapi: &mut Spec) {
+        let integer = |description: &str| {
+            HeaderBuilder::new()
+                .schema(ObjectBuilder::new().schema_type(SchemaType::Integer))
+                .description(Some(description))
+                .build()
+        };
+        let response = ResponseBuilder::new()
+            .description("The client's read or write budget is spent")
+            .header("Retry-After", integer("Seconds until a token is available"))
+            .header("RateLimit-Limit", integer("Size of the budget"))
+            .header(
+                "RateLimit-Remaining",
+                integer("Requests left in the budget"),
+            )
+            .header(
+                "RateLimit-Reset",
+                integer("Seconds until the budget is full"),
+            )
+            .content(
+                "application/json",
+                Content::new(Ref::from_schema_name("ErrorBody")),
+            )
+            .build();
+
+        for (path, item) in openapi.paths.paths.iter_mut() {
+            if !path.starts_with("/txt") {
+                continue;
+            }
+            for operation in item.operations.values_mut() {
+                operation
+                    .responses
+                    .responses
+                    .insert("429".to_owned(), response.clone().into());
+            }
+        }
+    }
+}
+
+/// The OpenAPI document served at `/openapi.json`.
+pub fn spec() -> Spec {
+    ApiDoc::openapi()
+}
+
+pub async fn openapi_json() -> Json<Spec> {
+    Json(spec())
+}
+
+/// Redoc page rendering `/openapi.json`, served at `/docs` when enabled.
+pub async fn docs() -> Html<&'static str> {
+    Html(
+        r#"<!DOCTYPE html>
+<html>
+  <head>
+    <title>shuttle-axum API</title>
+    <meta charset="utf-8" />
+    <meta name="viewport" content="width=device-width, initial-scale=1" />
+  </head>
+  <body>
+    <redoc spec-url="/openapi.json"></redoc>
+    <script src="https://cdn.jsdelivr.net/npm/redoc@2.0.0/bundles/redoc.standalone.js"></script>
+  </body>
+</html>
+"#,
+    )
+}
@@ -0,0 +1,86 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{error::AppError, trash::Trashed, Test};
+
+pub const DEFAULT_LIMIT: i64 = 50;
+pub const MAX_LIMIT: i64 = 100;
+
+/// Sort direction on `id`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Order {
+    #[default]
+    Asc,
+    Desc,
+}
+
+impl Order {
+    pub fn as_sql(self) -> &'static str {
+        match self {
+            Order::Asc => "ASC",
+            Order::Desc => "DESC",
+        }
+    }
+
+    /// Comparison that selects rows after the cursor in this direction.
+    pub fn after_sql(self) -> &'static str {
+        match self {
+            Order::Asc => ">",
+            Order::Desc => "<",
+        }
+    }
+}
+
+/// Opaque keyset cursor pointing at the last `id` of a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor(pub i32);
+
+impl Cursor {
+    pub fn encode(self) -> String {
+        URL_SAFE_NO_PAD.encode(self.0.to_string())
+    }
+
+    pub fn decode(cursor: &str) -> Result<Self, AppError> {
+        URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .and_then(|id| id.parse().ok())
+            .map(Cursor)
+            .ok_or_else(|| AppError::bad_request("invalid cursor"))
+    }
+}
+
+/// Validates a requested page size, falling back to the default.
+pub fn limit(limit: Option<i64>) -> Result<i64, AppError> {
+    match limit.unwrap_or(DEFAULT_LIMIT) {
+        limit @ 1..=MAX_LIMIT => Ok(limit),
+        _ => Err(AppError::bad_request(format!(
+            "limit must be between 1 and {MAX_LIMIT}"
+        ))),
+    }
+}
+
+/// A page of results and the cursor to fetch the next one, if any.
+#[derive(Debug, Serialize, ToSchema)]
+#[aliases(TestPage = Page<Test>, TrashPage = Page<Trashed>)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Pass as `cursor` to fetch the next page; `null` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// Builds a page from rows fetched with `limit + 1`, using the extra row
+    /// only to detect whether another page exists.
+    pub fn from_overfetch(mut items: Vec<T>, limit: i64, id: impl Fn(&T) -> i32) -> Self {
+        let has_more = items.len() as i64 > limit;
+        items.truncate(limit as usize);
+        let next_cursor = has_more
+            .then(|| items.last().map(|item| Cursor(id(item)).encode()))
+            .flatten();
+        Page { items, next_cursor }
+    }
+}
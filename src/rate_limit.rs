@@ -0,0 +1,328 @@
+//! Per-client token-bucket rate limiting for the `/txt` routes.
+//!
+//! Clients are identified by their API key, their JWT subject or, for
+//! anonymous reads, their IP address. Each client has one bucket for reads
+//! and one for writes, so a burst of writes does not lock it out of reading.
+//! Failed authentication spends a bucket of the IP address too, so keys
+//! cannot be guessed faster than the write quota allows.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, HeaderName, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use serde::Serialize;
+
+use crate::{
+    auth::{ApiKey, Owner, Scope},
+    config::Settings,
+    error::AppError,
+};
+
+/// Buckets kept before idle ones, which are full again, are dropped.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+const RETRY_AFTER: HeaderName = HeaderName::from_static("retry-after");
+
+const RATE_LIMITED: &str = "the rate limit has been exceeded, retry later";
+
+/// Size and refill rate of a bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Quota {
+    /// Requests a client can make at once.
+    pub burst: u32,
+    /// Requests a client can make per minute once the burst is spent.
+    pub per_minute: u32,
+}
+
+impl Quota {
+    fn refill_per_second(self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RateLimitConfig {
+    pub read: Quota,
+    pub write: Quota,
+    /// Identify anonymous clients by the last `X-Forwarded-For` entry even
+    /// when the peer address is known, i.e. behind a reverse proxy. Without
+    /// a peer address, as on Shuttle, the header is always used.
+    pub trust_proxy: bool,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            read: Quota {
+                burst: 60,
+                per_minute: 600,
+            },
+            write: Quota {
+                burst: 20,
+                per_minute: 60,
+            },
+            trust_proxy: false,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Reads the `RATE_LIMIT_*` settings, or `None` when
+    /// `RATE_LIMIT_ENABLED` is `false`. Unset quotas keep their defaults.
+    pub(crate) fn read(settings: &mut Settings) -> Option<Self> {
+        if !settings.parse("RATE_LIMIT_ENABLED", true) {
+            return None;
+        }
+        let defaults = RateLimitConfig::default();
+        let mut quota = |prefix: &str, default: Quota| {
+            let quota = Quota {
+                burst: settings.parse(&format!("{prefix}_BURST"), default.burst),
+                per_minute: settings.parse(&format!("{prefix}_PER_MINUTE"), default.per_minute),
+            };
+            settings.check(
+                quota.burst > 0 && quota.per_minute > 0,
+                &format!("{prefix}_BURST and {prefix}_PER_MINUTE must be positive"),
+            );
+            quota
+        };
+        let read = quota("RATE_LIMIT_READ", defaults.read);
+        let write = quota("RATE_LIMIT_WRITE", defaults.write);
+        Some(RateLimitConfig {
+            read,
+            write,
+            trust_proxy: settings.parse("RATE_LIMIT_TRUST_PROXY", defaults.trust_proxy),
+        })
+    }
+}
+
+/// Who a bucket belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Client {
+    ApiKey(i32),
+    User(String),
+    Ip(IpAddr),
+    /// Requests from an address that failed authentication.
+    FailedAuth(IpAddr),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Outcome of taking a token, with what the `RateLimit-*` headers report.
+#[derive(Debug)]
+struct Decision {
+    allowed: bool,
+    limit: u32,
+    remaining: u32,
+    /// Seconds until the bucket is full again.
+    reset: u64,
+    /// Seconds until the next token, when denied.
+    retry_after: u64,
+}
+
+/// Shared state of the [`limit`] middleware.
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Arc<Mutex<HashMap<(Client, Scope), Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            config,
+            buckets: Default::default(),
+        }
+    }
+
+    fn quota(&self, scope: Scope) -> Quota {
+        match scope {
+            Scope::Read => self.config.read,
+            Scope::ReadWrite => self.config.write,
+        }
+    }
+
+    fn take(&self, client: Client, scope: Scope, now: Instant) -> Decision {
+        self.refill(client, scope, now, true)
+    }
+
+    /// Like [`RateLimiter::take`], without spending the token.
+    fn peek(&self, client: Client, scope: Scope, now: Instant) -> Decision {
+        self.refill(client, scope, now, false)
+    }
+
+    fn refill(&self, client: Client, scope: Scope, now: Instant, spend: bool) -> Decision {
+        let quota = self.quota(scope);
+        let burst = f64::from(quota.burst);
+        let rate = quota.refill_per_second();
+
+        let mut buckets = self.buckets.lock().expect("rate limiter lock poisoned");
+        if buckets.len() >= PRUNE_THRESHOLD {
+            buckets.retain(|&(_, scope), bucket| {
+                let quota = self.quota(scope);
+                let idle = now.saturating_duration_since(bucket.updated).as_secs_f64();
+                bucket.tokens + idle * quota.refill_per_second() < f64::from(quota.burst)
+            });
+        }
+
+        let bucket = buckets.entry((client, scope)).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed && spend {
+            bucket.tokens -= 1.0;
+        }
+        Decision {
+            allowed,
+            limit: quota.burst,
+            remaining: bucket.tokens.floor() as u32,
+            reset: ((burst - bucket.tokens) / rate).ceil() as u64,
+            retry_after: ((1.0 - bucket.tokens).max(0.0) / rate).ceil() as u64,
+        }
+    }
+
+    /// Who sent `req`, or `None` for an anonymous request without an
+    /// address, which is not limited rather than sharing a bucket with every
+    /// other such request.
+    fn client<B>(&self, req: &Request<B>) -> Option<Client> {
+        let extensions = req.extensions();
+        if let Some(key) = extensions.get::<ApiKey>() {
+            return Some(Client::ApiKey(key.id));
+        }
+        if let Some(owner) = extensions
+            .get::<Owner>()
+            .filter(|owner| !owner.as_str().is_empty())
+        {
+            return Some(Client::User(owner.as_str().to_owned()));
+        }
+        self.address(req).map(Client::Ip)
+    }
+
+    fn address<B>(&self, req: &Request<B>) -> Option<IpAddr> {
+        let peer = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let forwarded = || forwarded_for(req.headers());
+        match peer {
+            Some(_) if self.config.trust_proxy => forwarded().or(peer),
+            Some(peer) => Some(peer),
+            None => forwarded(),
+        }
+    }
+}
+
+/// The buckets of the client behind a request, for requests that carry
+/// several operations such as WebSocket commands. Added to the extensions
+/// by [`limit`].
+#[derive(Debug, Clone)]
+pub(crate) struct Budget {
+    limiter: RateLimiter,
+    client: Client,
+}
+
+impl Budget {
+    /// Takes a token for one operation that needs `scope`.
+    pub(crate) fn take(&self, scope: Scope) -> Result<(), AppError> {
+        let decision = self
+            .limiter
+            .take(self.client.clone(), scope, Instant::now());
+        if decision.allowed {
+            Ok(())
+        } else {
+            Err(AppError::TooManyRequests(RATE_LIMITED))
+        }
+    }
+}
+
+/// The address the nearest proxy saw, which is the last one it appended.
+fn forwarded_for(headers: &HeaderMap) -> Option<IpAddr> {
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .last()
+        .and_then(|addr| addr.trim().parse().ok())
+}
+
+/// Takes a token from the client's read or write bucket, and rejects the
+/// request with a 429 when it is empty. Runs after [`crate::auth::authenticate`]
+/// so keys and users are told apart.
+pub async fn limit<B>(
+    State(limiter): State<RateLimiter>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let scope = Scope::required_for(req.method());
+    let Some(client) = limiter.client(&req) else {
+        return next.run(req).await;
+    };
+    let decision = limiter.take(client.clone(), scope, Instant::now());
+
+    let mut response = if decision.allowed {
+        req.extensions_mut().insert(Budget { limiter, client });
+        next.run(req).await
+    } else {
+        AppError::TooManyRequests(RATE_LIMITED).into_response()
+    };
+
+    let headers = response.headers_mut();
+    headers.insert(RATELIMIT_LIMIT, decision.limit.into());
+    headers.insert(RATELIMIT_REMAINING, decision.remaining.into());
+    headers.insert(RATELIMIT_RESET, decision.reset.into());
+    if !decision.allowed {
+        headers.insert(RETRY_AFTER, decision.retry_after.into());
+    }
+    response
+}
+
+/// Rejects requests from an address whose failed-authentication bucket,
+/// which has the write quota, is empty, and spends a token of it on every
+/// `401`. Runs before [`crate::auth::authenticate`], which never lets those
+/// requests reach [`limit`].
+pub async fn limit_failed_auth<B>(
+    State(limiter): State<RateLimiter>,
+    req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let Some(ip) = limiter.address(&req) else {
+        return next.run(req).await;
+    };
+    let client = Client::FailedAuth(ip);
+    let decision = limiter.peek(client.clone(), Scope::ReadWrite, Instant::now());
+    if !decision.allowed {
+        let mut response = AppError::TooManyRequests(RATE_LIMITED).into_response();
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, decision.retry_after.into());
+        return response;
+    }
+
+    let response = next.run(req).await;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        limiter.take(client, Scope::ReadWrite, Instant::now());
+    }
+    response
+}
@@ -0,0 +1,455 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    sync::{Arc, RwLock},
+};
+
+use axum::async_trait;
+use chrono::{DateTime, SubsecRound, Utc};
+
+use super::{ApiKeyRepository, DeleteManyOutcome, ListQuery, TestRepository, UpdateOutcome};
+use crate::{
+    auth::{self, ApiKey, Owner, Scope},
+    error::AppError,
+    events::{Change, ChangeFeed, ChangeKind, ChangeWindow, CHANGELOG_CAPACITY},
+    pagination::Order,
+    search::{Clause, SearchHit, SearchQuery, START_SEL, STOP_SEL},
+    trash::Trashed,
+    Test,
+};
+
+/// The current time at the microsecond precision of `timestamptz`.
+fn now() -> DateTime<Utc> {
+    Utc::now().trunc_subsecs(6)
+}
+
+#[derive(Debug)]
+struct Row {
+    owner: Owner,
+    test: Test,
+    deleted_at: Option<DateTime<Utc>>,
+}
+
+impl Row {
+    fn is_live_for(&self, owner: &Owner) -> bool {
+        &self.owner == owner && self.deleted_at.is_none()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Store {
+    rows: BTreeMap<i32, Row>,
+    last_id: i32,
+    changes: VecDeque<(Owner, Change)>,
+    last_change: i64,
+    api_keys: Vec<(Vec<u8>, ApiKey)>,
+}
+
+impl Store {
+    /// Appends to the changelog, as the trigger on `test` does.
+    fn record(&mut self, owner: &Owner, kind: ChangeKind, test: &Test) {
+        self.last_change += 1;
+        let change = Change {
+            id: self.last_change,
+            kind,
+            test: test.clone(),
+        };
+        self.changes.push_back((owner.clone(), change));
+        if self.changes.len() > CHANGELOG_CAPACITY {
+            self.changes.pop_front();
+        }
+    }
+
+    fn insert(&mut self, owner: &Owner, txt: &str) -> Test {
+        self.last_id += 1;
+        let now = now();
+        let test = Test {
+            id: self.last_id,
+            txt: txt.to_owned(),
+            version: 1,
+            created_at: now,
+            updated_at: now,
+        };
+        let row = Row {
+            owner: owner.clone(),
+            test: test.clone(),
+            deleted_at: None,
+        };
+        self.rows.insert(test.id, row);
+        self.record(owner, ChangeKind::Created, &test);
+        test
+    }
+
+    /// Live rows of `owner`, in `id` order.
+    fn owned<'a>(&'a self, owner: &'a Owner) -> impl DoubleEndedIterator<Item = &'a Test> {
+        self.rows
+            .values()
+            .filter(move |row| row.is_live_for(owner))
+            .map(|row| &row.test)
+    }
+
+    fn get(&self, owner: &Owner, id: i32) -> Option<&Test> {
+        self.rows
+            .get(&id)
+            .filter(|row| row.is_live_for(owner))
+            .map(|row| &row.test)
+    }
+
+    fn get_mut(&mut self, owner: &Owner, id: i32) -> Option<&mut Test> {
+        self.rows
+            .get_mut(&id)
+            .filter(|row| row.is_live_for(owner))
+            .map(|row| &mut row.test)
+    }
+
+    fn move_to_trash(&mut self, owner: &Owner, id: i32) -> Option<Test> {
+        let row = self
+            .rows
+            .get_mut(&id)
+            .filter(|row| row.is_live_for(owner))?;
+        row.deleted_at = Some(now());
+        let test = row.test.clone();
+        self.record(owner, ChangeKind::Deleted, &test);
+        Some(test)
+    }
+}
+
+/// Keeps the rows `query` selects, assuming `rows` are in `id` order.
+fn page<'a, T>(
+    rows: impl DoubleEndedIterator<Item = &'a T>,
+    query: &ListQuery,
+    test: impl Fn(&T) -> &Test,
+) -> Vec<&'a T> {
+    let q = query.q.as_deref().map(str::to_lowercase);
+    let matches = |row: &&T| {
+        let test = test(row);
+        let after_cursor = match (query.cursor, query.order) {
+            (None, _) => true,
+            (Some(cursor), Order::Asc) => test.id > cursor.0,
+            (Some(cursor), Order::Desc) => test.id < cursor.0,
+        };
+        let contains = q
+            .as_deref()
+            .is_none_or(|q| test.txt.to_lowercase().contains(q));
+        let updated = query.since.is_none_or(|since| test.updated_at > since);
+        after_cursor && contains && updated
+    };
+
+    let rows: Box<dyn Iterator<Item = &T>> = match query.order {
+        Order::Asc => Box::new(rows),
+        Order::Desc => Box::new(rows.rev()),
+    };
+    rows.filter(matches).take(query.limit as usize).collect()
+}
+
+/// [`TestRepository`] kept in process memory, for tests and local runs.
+///
+/// Search approximates the `simple` text search configuration: lexemes are
+/// lowercase alphanumeric runs and the rank is the share of matched lexemes.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryRepository {
+    store: Arc<RwLock<Store>>,
+    feed: ChangeFeed,
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers an API key, as inserting it into `api_keys` would.
+    pub fn with_api_key(self, name: &str, key: &str, scope: Scope) -> Self {
+        {
+            let mut store = self.write();
+            let id = store.api_keys.len() as i32 + 1;
+            let api_key = ApiKey {
+                id,
+                name: name.to_owned(),
+                scope,
+            };
+            store.api_keys.push((auth::hash_key(key), api_key));
+        }
+        self
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, Store> {
+        self.store.read().expect("memory store poisoned")
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, Store> {
+        self.store.write().expect("memory store poisoned")
+    }
+}
+
+#[async_trait]
+impl TestRepository for MemoryRepository {
+    async fn get(&self, owner: &Owner, id: i32) -> Result<Option<Test>, AppError> {
+        Ok(self.read().get(owner, id).cloned())
+    }
+
+    async fn list(&self, owner: &Owner, query: &ListQuery) -> Result<Vec<Test>, AppError> {
+        let store = self.read();
+        let tests = page(store.owned(owner), query, |test| test);
+        Ok(tests.into_iter().cloned().collect())
+    }
+
+    async fn search(
+        &self,
+        owner: &Owner,
+        query: &SearchQuery,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>, AppError> {
+        let store = self.read();
+        let mut hits: Vec<SearchHit> = store
+            .owned(owner)
+            .filter_map(|test| search_hit(test, query))
+            .collect();
+        hits.sort_by(|a, b| b.rank.total_cmp(&a.rank).then(a.test.id.cmp(&b.test.id)));
+        hits.truncate(limit as usize);
+        Ok(hits)
+    }
+
+    async fn create(&self, owner: &Owner, txt: &str) -> Result<Test, AppError> {
+        let test = self.write().insert(owner, txt);
+        self.feed.notify();
+        Ok(test)
+    }
+
+    async fn create_many(&self, owner: &Owner, txts: &[&str]) -> Result<Vec<Test>, AppError> {
+        let tests = {
+            let mut store = self.write();
+            txts.iter().map(|txt| store.insert(owner, txt)).collect()
+        };
+        self.feed.notify();
+        Ok(tests)
+    }
+
+    async fn update(
+        &self,
+        owner: &Owner,
+        id: i32,
+        txt: &str,
+        expected: Option<&[i32]>,
+    ) -> Result<UpdateOutcome, AppError> {
+        let mut store = self.write();
+        let Some(test) = store.get_mut(owner, id) else {
+            return Ok(UpdateOutcome::NotFound);
+        };
+        if expected.is_some_and(|versions| !versions.contains(&test.version)) {
+            return Ok(UpdateOutcome::VersionMismatch);
+        }
+        test.txt = txt.to_owned();
+        test.version += 1;
+        test.updated_at = now();
+        let test = test.clone();
+        store.record(owner, ChangeKind::Updated, &test);
+        drop(store);
+        self.feed.notify();
+        Ok(UpdateOutcome::Updated(test))
+    }
+
+    async fn delete(&self, owner: &Owner, id: i32) -> Result<Option<Test>, AppError> {
+        let test = self.write().move_to_trash(owner, id);
+        self.feed.notify();
+        Ok(test)
+    }
+
+    async fn delete_many(
+        &self,
+        owner: &Owner,
+        ids: &[i32],
+        all_or_nothing: bool,
+    ) -> Result<DeleteManyOutcome, AppError> {
+        let unique: BTreeSet<i32> = ids.iter().copied().collect();
+        let mut store = self.write();
+
+        if all_or_nothing {
+            let missing: Vec<i32> = unique
+                .iter()
+                .copied()
+                .filter(|&id| store.get(owner, id).is_none())
+                .collect();
+            if !missing.is_empty() {
+                return Ok(DeleteManyOutcome::Missing(missing));
+            }
+        }
+
+        let deleted = unique
+            .iter()
+            .filter_map(|&id| store.move_to_trash(owner, id))
+            .collect();
+        drop(store);
+        self.feed.notify();
+        Ok(DeleteManyOutcome::Deleted(deleted))
+    }
+
+    async fn trash(&self, owner: &Owner, query: &ListQuery) -> Result<Vec<Trashed>, AppError> {
+        let store = self.read();
+        let trashed: Vec<Trashed> = store
+            .rows
+            .values()
+            .filter(|row| &row.owner == owner)
+            .filter_map(|row| {
+                Some(Trashed {
+                    test: row.test.clone(),
+                    deleted_at: row.deleted_at?,
+                })
+            })
+            .collect();
+        Ok(page(trashed.iter(), query, |row| &row.test)
+            .into_iter()
+            .cloned()
+            .collect())
+    }
+
+    async fn restore(&self, owner: &Owner, id: i32) -> Result<Option<Test>, AppError> {
+        let mut store = self.write();
+        let Some(row) = store
+            .rows
+            .get_mut(&id)
+            .filter(|row| &row.owner == owner && row.deleted_at.is_some())
+        else {
+            return Ok(None);
+        };
+        row.deleted_at = None;
+        row.test.version += 1;
+        row.test.updated_at = now();
+        let test = row.test.clone();
+        store.record(owner, ChangeKind::Created, &test);
+        drop(store);
+        self.feed.notify();
+        Ok(Some(test))
+    }
+
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, AppError> {
+        let mut store = self.write();
+        let count = store.rows.len();
+        store
+            .rows
+            .retain(|_, row| row.deleted_at.is_none_or(|deleted_at| deleted_at >= before));
+        Ok((count - store.rows.len()) as u64)
+    }
+
+    async fn changes(
+        &self,
+        owner: &Owner,
+        after: i64,
+        limit: i64,
+    ) -> Result<Vec<Change>, AppError> {
+        let store = self.read();
+        Ok(store
+            .changes
+            .iter()
+            .filter(|(changed_by, change)| changed_by == owner && change.id > after)
+            .map(|(_, change)| change.clone())
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn change_window(&self) -> Result<ChangeWindow, AppError> {
+        let store = self.read();
+        let first = store
+            .changes
+            .front()
+            .map_or(store.last_change + 1, |(_, change)| change.id);
+        Ok(ChangeWindow {
+            first,
+            last: store.last_change,
+        })
+    }
+
+    fn change_feed(&self) -> &ChangeFeed {
+        &self.feed
+    }
+
+    async fn ping(&self) -> Result<(), AppError> {
+        Ok(())
+    }
+
+    async fn schema_ready(&self) -> Result<bool, AppError> {
+        Ok(true)
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for MemoryRepository {
+    async fn find_api_key(&self, key_hash: &[u8]) -> Result<Option<ApiKey>, AppError> {
+        let store = self.read();
+        Ok(store
+            .api_keys
+            .iter()
+            .find(|(hash, _)| hash == key_hash)
+            .map(|(_, key)| key.clone()))
+    }
+}
+
+/// Byte offsets and text of the alphanumeric runs in `text`.
+fn lexeme_spans(text: &str) -> Vec<(usize, &str)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                spans.push((s, &text[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        spans.push((s, &text[s..]));
+    }
+    spans
+}
+
+/// Matches `test` against every clause, marking the matched lexemes.
+fn search_hit(test: &Test, query: &SearchQuery) -> Option<SearchHit> {
+    let spans = lexeme_spans(&test.txt);
+    let lexemes: Vec<String> = spans.iter().map(|(_, s)| s.to_lowercase()).collect();
+
+    let mut matched = vec![false; lexemes.len()];
+    for clause in query.clauses() {
+        let mut found = false;
+        match clause {
+            Clause::Term { lexeme, prefix } => {
+                for (i, candidate) in lexemes.iter().enumerate() {
+                    if candidate == lexeme || (*prefix && candidate.starts_with(lexeme.as_str())) {
+                        matched[i] = true;
+                        found = true;
+                    }
+                }
+            }
+            Clause::Phrase(phrase) => {
+                for start in 0..lexemes.len().saturating_sub(phrase.len() - 1) {
+                    if lexemes[start..start + phrase.len()] == phrase[..] {
+                        matched[start..start + phrase.len()].fill(true);
+                        found = true;
+                    }
+                }
+            }
+        }
+        if !found {
+            return None;
+        }
+    }
+
+    let mut snippet = String::with_capacity(test.txt.len() + 2 * matched.len());
+    let mut copied = 0;
+    for ((start, lexeme), matched) in spans.iter().zip(&matched) {
+        if *matched {
+            snippet.push_str(&test.txt[copied..*start]);
+            snippet.push(START_SEL);
+            snippet.push_str(lexeme);
+            snippet.push(STOP_SEL);
+            copied = start + lexeme.len();
+        }
+    }
+    snippet.push_str(&test.txt[copied..]);
+
+    let rank = matched.iter().filter(|m| **m).count() as f32 / lexemes.len() as f32;
+    Some(SearchHit {
+        test: test.clone(),
+        rank,
+        snippet,
+    })
+}
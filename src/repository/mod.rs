@@ -0,0 +1,148 @@
+//! Storage for `Test` rows and API keys.
+//!
+//! Handlers only talk to [`TestRepository`], so all SQL lives in
+//! [`postgres`] and handler tests can run against [`memory`] instead.
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+
+use crate::{
+    auth::{ApiKey, Owner},
+    error::AppError,
+    Test,
+};
+
+pub mod memory;
+pub mod postgres;
+
+pub use crate::{
+    events::{Change, ChangeFeed, ChangeKind, ChangeWindow},
+    pagination::{Cursor, Order},
+    search::{Clause, SearchHit, SearchQuery},
+    trash::Trashed,
+};
+pub use memory::MemoryRepository;
+pub use postgres::PgRepository;
+
+/// Filters and keyset position for listing rows.
+#[derive(Debug, Clone, Default)]
+pub struct ListQuery {
+    pub cursor: Option<Cursor>,
+    pub order: Order,
+    /// Case-insensitive substring filter on `txt`.
+    pub q: Option<String>,
+    /// Only rows updated after this instant.
+    pub since: Option<DateTime<Utc>>,
+    pub limit: i64,
+}
+
+/// Result of a conditional update.
+#[derive(Debug)]
+pub enum UpdateOutcome {
+    Updated(Test),
+    NotFound,
+    /// The row exists but its version is not one of the expected ones.
+    VersionMismatch,
+}
+
+/// Result of deleting several rows at once.
+#[derive(Debug)]
+pub enum DeleteManyOutcome {
+    Deleted(Vec<Test>),
+    /// Nothing was deleted because these ids do not exist.
+    Missing(Vec<i32>),
+}
+
+/// Connection pool statistics reported by readiness checks.
+#[derive(Debug, Clone, Copy)]
+pub struct PoolStats {
+    pub size: u32,
+    pub idle: usize,
+}
+
+/// Row storage. Every row belongs to an [`Owner`], and rows of other owners
+/// behave as if they did not exist.
+///
+/// Deleting moves a row to the trash, where only [`TestRepository::trash`],
+/// [`TestRepository::restore`] and [`TestRepository::purge`] see it.
+///
+/// Writes to live rows are also recorded in a bounded changelog, read
+/// through [`TestRepository::changes`].
+#[async_trait]
+pub trait TestRepository: Clone + Send + Sync + 'static {
+    async fn get(&self, owner: &Owner, id: i32) -> Result<Option<Test>, AppError>;
+
+    /// Returns up to `query.limit` rows ordered by `id`.
+    async fn list(&self, owner: &Owner, query: &ListQuery) -> Result<Vec<Test>, AppError>;
+
+    async fn search(
+        &self,
+        owner: &Owner,
+        query: &SearchQuery,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>, AppError>;
+
+    async fn create(&self, owner: &Owner, txt: &str) -> Result<Test, AppError>;
+
+    /// Inserts all values at once, returning rows in input order.
+    async fn create_many(&self, owner: &Owner, txts: &[&str]) -> Result<Vec<Test>, AppError>;
+
+    /// Replaces `txt` and bumps the version. With `expected` set, the update
+    /// only applies when the current version is one of them.
+    async fn update(
+        &self,
+        owner: &Owner,
+        id: i32,
+        txt: &str,
+        expected: Option<&[i32]>,
+    ) -> Result<UpdateOutcome, AppError>;
+
+    /// Moves a row to the trash.
+    async fn delete(&self, owner: &Owner, id: i32) -> Result<Option<Test>, AppError>;
+
+    /// Moves the given ids to the trash. With `all_or_nothing`, nothing is deleted
+    /// unless every id exists.
+    async fn delete_many(
+        &self,
+        owner: &Owner,
+        ids: &[i32],
+        all_or_nothing: bool,
+    ) -> Result<DeleteManyOutcome, AppError>;
+
+    /// Returns up to `query.limit` trashed rows ordered by `id`.
+    async fn trash(&self, owner: &Owner, query: &ListQuery) -> Result<Vec<Trashed>, AppError>;
+
+    /// Takes a row out of the trash, bumping its version and `updated_at`.
+    async fn restore(&self, owner: &Owner, id: i32) -> Result<Option<Test>, AppError>;
+
+    /// Permanently removes rows of every owner trashed before `before`,
+    /// returning how many there were.
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, AppError>;
+
+    /// Changes to rows of `owner` with ids after `after`, oldest first.
+    async fn changes(&self, owner: &Owner, after: i64, limit: i64)
+        -> Result<Vec<Change>, AppError>;
+
+    /// The ids still held by the changelog.
+    async fn change_window(&self) -> Result<ChangeWindow, AppError>;
+
+    /// Notified after writes, so event streams look for new changes.
+    fn change_feed(&self) -> &ChangeFeed;
+
+    /// Checks that the backing store answers.
+    async fn ping(&self) -> Result<(), AppError>;
+
+    /// Checks that the `test` table exists.
+    async fn schema_ready(&self) -> Result<bool, AppError>;
+
+    /// Connection pool statistics, for stores that have a pool.
+    fn pool_stats(&self) -> Option<PoolStats> {
+        None
+    }
+}
+
+#[async_trait]
+pub trait ApiKeyRepository: Clone + Send + Sync + 'static {
+    /// Looks up an active (not revoked) key by the hash of its value.
+    async fn find_api_key(&self, key_hash: &[u8]) -> Result<Option<ApiKey>, AppError>;
+}
@@ -0,0 +1,413 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use axum::async_trait;
+use chrono::{DateTime, Utc};
+use shuttle_runtime::tracing;
+use sqlx::{postgres::PgListener, types::Json, Execute, PgPool, Postgres, QueryBuilder};
+use tokio::task::JoinHandle;
+
+use super::{
+    ApiKeyRepository, DeleteManyOutcome, ListQuery, PoolStats, TestRepository, UpdateOutcome,
+};
+use crate::{
+    auth::{ApiKey, Owner},
+    error::AppError,
+    events::{Change, ChangeFeed, ChangeWindow},
+    metrics::time_query,
+    search::{SearchHit, SearchQuery, START_SEL, STOP_SEL},
+    telemetry::{traced, traced_one},
+    trash::Trashed,
+    Test,
+};
+
+/// Channel the triggers on `test` notify, in
+/// `migrations/0009_notify_test_changes.sql`.
+const CHANGES_CHANNEL: &str = "test_changes";
+
+/// Pause before listening again after the listener connection failed.
+const RELISTEN_DELAY: Duration = Duration::from_secs(1);
+
+/// [`TestRepository`] backed by the `test` table.
+///
+/// The `test_changes` changelog is written by a trigger on `test`. Writes
+/// through this repository wake its event streams directly; see
+/// [`PgRepository::spawn_listener`] for writes made by other instances.
+#[derive(Debug, Clone)]
+pub struct PgRepository {
+    pool: PgPool,
+    feed: ChangeFeed,
+}
+
+impl PgRepository {
+    pub fn new(pool: PgPool) -> Self {
+        PgRepository {
+            pool,
+            feed: ChangeFeed::default(),
+        }
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// Listens for the changes every instance notifies and wakes the event
+    /// streams of this one, for as long as the pool is open.
+    ///
+    /// Returns once `LISTEN` is in effect. Changes notified while the
+    /// connection is down are only delayed until the next notification,
+    /// since streams read them from the changelog.
+    pub async fn spawn_listener(&self) -> Result<JoinHandle<()>, sqlx::Error> {
+        let mut listener = PgListener::connect_with(&self.pool).await?;
+        listener.listen(CHANGES_CHANNEL).await?;
+
+        let feed = self.feed.clone();
+        Ok(tokio::spawn(async move {
+            loop {
+                match listener.try_recv().await {
+                    Ok(Some(_)) => feed.notify(),
+                    // Reconnects and listens again on the next call.
+                    Ok(None) => {
+                        tracing::warn!("change listener lost its connection");
+                        feed.notify();
+                    }
+                    Err(sqlx::Error::PoolClosed) => break,
+                    Err(error) => {
+                        tracing::warn!(?error, "change listener failed");
+                        tokio::time::sleep(RELISTEN_DELAY).await;
+                    }
+                }
+            }
+        }))
+    }
+}
+
+/// Selects `columns` of the live or trashed rows of `owner` on the page
+/// described by `list`.
+fn page_query<'a>(
+    columns: &str,
+    trashed: bool,
+    owner: &'a Owner,
+    list: &'a ListQuery,
+) -> QueryBuilder<'a, Postgres> {
+    let deleted = if trashed { "IS NOT NULL" } else { "IS NULL" };
+    let mut query = QueryBuilder::<Postgres>::new(format!(
+        "SELECT {columns} FROM test WHERE deleted_at {deleted} AND owner_id = "
+    ));
+    query.push_bind(owner.as_str());
+    if let Some(cursor) = list.cursor {
+        query
+            .push(format_args!(" AND id {} ", list.order.after_sql()))
+            .push_bind(cursor.0);
+    }
+    if let Some(since) = list.since {
+        query.push(" AND updated_at > ").push_bind(since);
+    }
+    if let Some(q) = list.q.as_deref().filter(|q| !q.is_empty()) {
+        query
+            .push(" AND strpos(lower(txt), lower(")
+            .push_bind(q)
+            .push(")) > 0");
+    }
+    query
+        .push(format_args!(" ORDER BY id {} LIMIT ", list.order.as_sql()))
+        .push_bind(list.limit);
+    query
+}
+
+#[async_trait]
+impl TestRepository for PgRepository {
+    async fn get(&self, owner: &Owner, id: i32) -> Result<Option<Test>, AppError> {
+        let query = sqlx::query_as::<_, Test>(
+            "SELECT id, txt, version, created_at, updated_at FROM test \
+             WHERE id = $1 AND owner_id = $2 AND deleted_at IS NULL",
+        )
+        .bind(id)
+        .bind(owner.as_str());
+        let test = traced(query.sql(), query.fetch_optional(&self.pool)).await?;
+        Ok(test)
+    }
+
+    async fn list(&self, owner: &Owner, list: &ListQuery) -> Result<Vec<Test>, AppError> {
+        let mut query = page_query(
+            "id, txt, version, created_at, updated_at",
+            false,
+            owner,
+            list,
+        );
+        let query = query.build_query_as::<Test>();
+        let query = traced(query.sql(), query.fetch_all(&self.pool));
+        let tests = time_query("list_tests", query).await?;
+        Ok(tests)
+    }
+
+    async fn search(
+        &self,
+        owner: &Owner,
+        query: &SearchQuery,
+        limit: i64,
+    ) -> Result<Vec<SearchHit>, AppError> {
+        let sql = format!(
+            "SELECT id, txt, version, created_at, updated_at, ts_rank(txt_search, query) AS rank, \
+             ts_headline('simple', txt, query, 'StartSel={START_SEL}, StopSel={STOP_SEL}') AS snippet \
+             FROM test, to_tsquery('simple', $1) AS query \
+             WHERE owner_id = $3 AND deleted_at IS NULL AND txt_search @@ query \
+             ORDER BY rank DESC, id ASC \
+             LIMIT $2"
+        );
+        let hits = sqlx::query_as::<_, SearchHit>(&sql)
+            .bind(query.to_tsquery())
+            .bind(limit)
+            .bind(owner.as_str())
+            .fetch_all(&self.pool);
+        let hits = traced(&sql, hits).await?;
+        Ok(hits)
+    }
+
+    async fn create(&self, owner: &Owner, txt: &str) -> Result<Test, AppError> {
+        let query = sqlx::query_as::<_, Test>(
+            "INSERT INTO test (txt, owner_id) VALUES ($1, $2) \
+             RETURNING id, txt, version, created_at, updated_at",
+        )
+        .bind(txt)
+        .bind(owner.as_str());
+        let query = traced_one(query.sql(), query.fetch_one(&self.pool));
+        let test = time_query("create_test", query).await?;
+        self.feed.notify();
+        Ok(test)
+    }
+
+    async fn create_many(&self, owner: &Owner, txts: &[&str]) -> Result<Vec<Test>, AppError> {
+        if txts.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let query = sqlx::query_as::<_, Test>(
+            "INSERT INTO test (txt, owner_id) SELECT txt, $2 FROM UNNEST($1::varchar[]) AS t (txt) \
+             RETURNING id, txt, version, created_at, updated_at",
+        )
+        .bind(txts)
+        .bind(owner.as_str());
+        let mut tests = traced(query.sql(), query.fetch_all(&mut tx)).await?;
+        tx.commit().await?;
+        self.feed.notify();
+
+        // Ids come from a sequence consumed in UNNEST order.
+        tests.sort_by_key(|test| test.id);
+        Ok(tests)
+    }
+
+    async fn update(
+        &self,
+        owner: &Owner,
+        id: i32,
+        txt: &str,
+        expected: Option<&[i32]>,
+    ) -> Result<UpdateOutcome, AppError> {
+        let query = match expected {
+            None => sqlx::query_as::<_, Test>(
+                "UPDATE test SET txt = $2, version = version + 1, updated_at = now() \
+                 WHERE id = $1 AND owner_id = $3 AND deleted_at IS NULL \
+                 RETURNING id, txt, version, created_at, updated_at",
+            )
+            .bind(id)
+            .bind(txt)
+            .bind(owner.as_str()),
+            Some(versions) => sqlx::query_as::<_, Test>(
+                "UPDATE test SET txt = $2, version = version + 1, updated_at = now() \
+                 WHERE id = $1 AND owner_id = $3 AND deleted_at IS NULL AND version = ANY($4) \
+                 RETURNING id, txt, version, created_at, updated_at",
+            )
+            .bind(id)
+            .bind(txt)
+            .bind(owner.as_str())
+            .bind(versions),
+        };
+        let test = traced(query.sql(), query.fetch_optional(&self.pool)).await?;
+
+        match test {
+            Some(test) => {
+                self.feed.notify();
+                Ok(UpdateOutcome::Updated(test))
+            }
+            None if expected.is_none() => Ok(UpdateOutcome::NotFound),
+            None => {
+                // Either the row is gone or someone else updated it first.
+                let query = sqlx::query(
+                    "SELECT 1 FROM test WHERE id = $1 AND owner_id = $2 AND deleted_at IS NULL",
+                )
+                .bind(id)
+                .bind(owner.as_str());
+                let exists = traced(query.sql(), query.fetch_optional(&self.pool))
+                    .await?
+                    .is_some();
+                Ok(if exists {
+                    UpdateOutcome::VersionMismatch
+                } else {
+                    UpdateOutcome::NotFound
+                })
+            }
+        }
+    }
+
+    async fn delete(&self, owner: &Owner, id: i32) -> Result<Option<Test>, AppError> {
+        let query = sqlx::query_as::<_, Test>(
+            "UPDATE test SET deleted_at = now() \
+             WHERE id = $1 AND owner_id = $2 AND deleted_at IS NULL \
+             RETURNING id, txt, version, created_at, updated_at",
+        )
+        .bind(id)
+        .bind(owner.as_str());
+        let query = traced(query.sql(), query.fetch_optional(&self.pool));
+        let test = time_query("delete_test", query).await?;
+        if test.is_some() {
+            self.feed.notify();
+        }
+        Ok(test)
+    }
+
+    async fn delete_many(
+        &self,
+        owner: &Owner,
+        ids: &[i32],
+        all_or_nothing: bool,
+    ) -> Result<DeleteManyOutcome, AppError> {
+        let unique: BTreeSet<i32> = ids.iter().copied().collect();
+        let unique: Vec<i32> = unique.into_iter().collect();
+
+        let mut tx = self.pool.begin().await?;
+        let query = sqlx::query_as::<_, Test>(
+            "UPDATE test SET deleted_at = now() \
+             WHERE id = ANY($1) AND owner_id = $2 AND deleted_at IS NULL \
+             RETURNING id, txt, version, created_at, updated_at",
+        )
+        .bind(&unique)
+        .bind(owner.as_str());
+        let tests = traced(query.sql(), query.fetch_all(&mut tx)).await?;
+
+        if all_or_nothing && tests.len() != unique.len() {
+            tx.rollback().await?;
+            let deleted: BTreeSet<i32> = tests.iter().map(|test| test.id).collect();
+            let missing = unique
+                .into_iter()
+                .filter(|id| !deleted.contains(id))
+                .collect();
+            return Ok(DeleteManyOutcome::Missing(missing));
+        }
+        tx.commit().await?;
+        if !tests.is_empty() {
+            self.feed.notify();
+        }
+        Ok(DeleteManyOutcome::Deleted(tests))
+    }
+
+    async fn trash(&self, owner: &Owner, list: &ListQuery) -> Result<Vec<Trashed>, AppError> {
+        let mut query = page_query(
+            "id, txt, version, created_at, updated_at, deleted_at",
+            true,
+            owner,
+            list,
+        );
+        let query = query.build_query_as::<Trashed>();
+        let trashed = traced(query.sql(), query.fetch_all(&self.pool)).await?;
+        Ok(trashed)
+    }
+
+    async fn restore(&self, owner: &Owner, id: i32) -> Result<Option<Test>, AppError> {
+        let query = sqlx::query_as::<_, Test>(
+            "UPDATE test SET deleted_at = NULL, version = version + 1, updated_at = now() \
+             WHERE id = $1 AND owner_id = $2 AND deleted_at IS NOT NULL \
+             RETURNING id, txt, version, created_at, updated_at",
+        )
+        .bind(id)
+        .bind(owner.as_str());
+        let test = traced(query.sql(), query.fetch_optional(&self.pool)).await?;
+        if test.is_some() {
+            self.feed.notify();
+        }
+        Ok(test)
+    }
+
+    async fn purge(&self, before: DateTime<Utc>) -> Result<u64, AppError> {
+        let query = sqlx::query("DELETE FROM test WHERE deleted_at < $1").bind(before);
+        let purged = traced(query.sql(), query.execute(&self.pool))
+            .await?
+            .rows_affected();
+        Ok(purged)
+    }
+
+    async fn changes(
+        &self,
+        owner: &Owner,
+        after: i64,
+        limit: i64,
+    ) -> Result<Vec<Change>, AppError> {
+        let query = sqlx::query_as::<_, (i64, String, Json<Test>)>(
+            "SELECT id, kind, test FROM test_changes \
+             WHERE owner_id = $1 AND id > $2 ORDER BY id LIMIT $3",
+        )
+        .bind(owner.as_str())
+        .bind(after)
+        .bind(limit);
+        let rows = traced(query.sql(), query.fetch_all(&self.pool)).await?;
+
+        rows.into_iter()
+            .map(|(id, kind, Json(test))| {
+                let kind = kind
+                    .parse()
+                    .map_err(|error: String| AppError::Internal(error.into()))?;
+                Ok(Change { id, kind, test })
+            })
+            .collect()
+    }
+
+    async fn change_window(&self) -> Result<ChangeWindow, AppError> {
+        let query = sqlx::query_as::<_, (i64, i64)>(
+            "SELECT coalesce(min(id), 1), coalesce(max(id), 0) FROM test_changes",
+        );
+        let (first, last) = traced_one(query.sql(), query.fetch_one(&self.pool)).await?;
+        Ok(ChangeWindow { first, last })
+    }
+
+    fn change_feed(&self) -> &ChangeFeed {
+        &self.feed
+    }
+
+    async fn ping(&self) -> Result<(), AppError> {
+        let query = sqlx::query("SELECT 1");
+        traced(query.sql(), query.execute(&self.pool)).await?;
+        Ok(())
+    }
+
+    async fn schema_ready(&self) -> Result<bool, AppError> {
+        let query = sqlx::query_scalar::<_, bool>("SELECT to_regclass('test') IS NOT NULL");
+        let exists = traced_one(query.sql(), query.fetch_one(&self.pool)).await?;
+        Ok(exists)
+    }
+
+    fn pool_stats(&self) -> Option<PoolStats> {
+        Some(PoolStats {
+            size: self.pool.size(),
+            idle: self.pool.num_idle(),
+        })
+    }
+}
+
+#[async_trait]
+impl ApiKeyRepository for PgRepository {
+    async fn find_api_key(&self, key_hash: &[u8]) -> Result<Option<ApiKey>, AppError> {
+        let query = sqlx::query_as::<_, (i32, String, String)>(
+            "SELECT id, name, scope FROM api_keys WHERE key_hash = $1 AND revoked_at IS NULL",
+        )
+        .bind(key_hash);
+        let row = traced(query.sql(), query.fetch_optional(&self.pool)).await?;
+
+        row.map(|(id, name, scope)| {
+            let scope = scope
+                .parse()
+                .map_err(|error: String| AppError::Internal(error.into()))?;
+            Ok(ApiKey { id, name, scope })
+        })
+        .transpose()
+    }
+}
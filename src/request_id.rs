@@ -0,0 +1,113 @@
+//! `X-Request-Id` handling and the span every request runs in.
+//!
+//! The id a client sends is kept when it is reasonable, so a request can be
+//! followed from a proxy through to this service's logs and traces. It is
+//! echoed in the response and in the `request_id` of error bodies.
+
+use std::{fmt, sync::Arc, time::Instant};
+
+use axum::{
+    extract::MatchedPath,
+    http::{HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use shuttle_runtime::tracing::{self, field::Empty, Instrument};
+use uuid::Uuid;
+
+use crate::metrics::UNMATCHED;
+
+pub const X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied id that is kept.
+const MAX_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: RequestId;
+}
+
+/// The id of a request, available as an extension to handlers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(Arc<str>);
+
+impl RequestId {
+    fn generate() -> Self {
+        RequestId(Uuid::new_v4().to_string().into())
+    }
+
+    /// Keeps a client's id if it is short printable ASCII, which is safe to
+    /// log and to send back as a header.
+    fn from_header(value: &HeaderValue) -> Option<Self> {
+        let id = value.to_str().ok()?;
+        let valid =
+            !id.is_empty() && id.len() <= MAX_LEN && id.bytes().all(|byte| byte.is_ascii_graphic());
+        valid.then(|| RequestId(id.into()))
+    }
+
+    /// The id of the request being handled by this task, if any.
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(RequestId::clone).ok()
+    }
+
+    /// Runs `future` as part of this request, for work that outlives the
+    /// handler such as a WebSocket session.
+    pub(crate) async fn scope<F: std::future::Future>(self, future: F) -> F::Output {
+        CURRENT.scope(self, future).await
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Accepts or generates the request id and runs the request in a span
+/// named after its matched route, which SQL spans nest under.
+pub async fn trace<B>(
+    matched: Option<MatchedPath>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let id = req
+        .headers()
+        .get(X_REQUEST_ID)
+        .and_then(RequestId::from_header)
+        .unwrap_or_else(RequestId::generate);
+    let route = matched.as_ref().map_or(UNMATCHED, MatchedPath::as_str);
+    let method = req.method().clone();
+    let span = tracing::info_span!(
+        "request",
+        otel.name = %format_args!("{method} {route}"),
+        otel.kind = "server",
+        request_id = %id,
+        http.method = %method,
+        http.route = route,
+        http.status_code = Empty,
+    );
+    req.extensions_mut().insert(id.clone());
+
+    let started = Instant::now();
+    let mut response = id
+        .clone()
+        .scope(next.run(req))
+        .instrument(span.clone())
+        .await;
+
+    let status = response.status().as_u16();
+    span.record("http.status_code", status);
+    span.in_scope(|| {
+        tracing::info!(
+            status,
+            latency_ms = started.elapsed().as_millis() as u64,
+            "request finished"
+        )
+    });
+    let value = HeaderValue::from_str(id.as_str()).expect("request ids are valid headers");
+    response.headers_mut().insert(X_REQUEST_ID, value);
+    response
+}
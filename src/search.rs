@@ -0,0 +1,160 @@
+use axum::{
+    extract::{rejection::QueryRejection, Query, State},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{auth::Owner, error::AppError, pagination, repository::TestRepository, Test};
+
+/// Markers stores put around matches before the snippet is escaped.
+/// Control characters are used so they cannot be confused with HTML.
+pub const START_SEL: char = '\u{2}';
+pub const STOP_SEL: char = '\u{3}';
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchParams {
+    /// Terms that must all match; `term*` matches a prefix and `"a b"` a
+    /// phrase.
+    q: String,
+    /// Maximum number of hits, 1 to 100; defaults to 50.
+    limit: Option<i64>,
+}
+
+/// A search match, ranked by relevance.
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub test: Test,
+    pub rank: f32,
+    /// HTML-escaped excerpt with matches wrapped in `<mark>` tags.
+    pub snippet: String,
+}
+
+/// One condition of a search; every clause must match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Clause {
+    Term { lexeme: String, prefix: bool },
+    Phrase(Vec<String>),
+}
+
+/// A parsed search query.
+///
+/// `q` is a list of terms that must all match. A term ending in `*` matches
+/// as a prefix and a double-quoted group of terms matches as a phrase, e.g.
+/// `"shuttle rocks" ax*`. Only alphanumeric runs are kept as lexemes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchQuery {
+    clauses: Vec<Clause>,
+}
+
+impl SearchQuery {
+    /// Returns `None` when `q` contains no searchable terms.
+    pub fn parse(q: &str) -> Option<Self> {
+        let mut clauses = Vec::new();
+        for (i, part) in q.split('"').enumerate() {
+            let in_phrase = i % 2 == 1;
+            if in_phrase {
+                let lexemes = lexemes(part);
+                if !lexemes.is_empty() {
+                    clauses.push(Clause::Phrase(lexemes));
+                }
+                continue;
+            }
+            for word in part.split_whitespace() {
+                let prefix = word.ends_with('*');
+                let mut lexemes = lexemes(word);
+                match lexemes.len() {
+                    0 => {}
+                    1 => clauses.push(Clause::Term {
+                        lexeme: lexemes.remove(0),
+                        prefix,
+                    }),
+                    // Words like `foo-bar` are indexed as separate lexemes.
+                    _ => clauses.push(Clause::Phrase(lexemes)),
+                }
+            }
+        }
+        (!clauses.is_empty()).then_some(SearchQuery { clauses })
+    }
+
+    pub fn clauses(&self) -> &[Clause] {
+        &self.clauses
+    }
+
+    /// Renders the query as a Postgres `tsquery` expression.
+    pub fn to_tsquery(&self) -> String {
+        self.clauses
+            .iter()
+            .map(|clause| match clause {
+                Clause::Term {
+                    lexeme,
+                    prefix: true,
+                } => format!("{lexeme}:*"),
+                Clause::Term { lexeme, .. } => lexeme.clone(),
+                Clause::Phrase(lexemes) => format!("({})", lexemes.join(" <-> ")),
+            })
+            .collect::<Vec<_>>()
+            .join(" & ")
+    }
+}
+
+/// Splits text into lowercase alphanumeric lexemes, as the `simple` text
+/// search configuration does.
+pub fn lexemes(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|lexeme| !lexeme.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Searches `txt`, best matches first.
+#[utoipa::path(
+    get,
+    path = "/txt/search",
+    tag = "txt",
+    params(SearchParams),
+    responses(
+        (status = 200, description = "Matches, best first", body = [SearchHit]),
+        (status = 400, description = "`q` has no search terms or `limit` is invalid", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub async fn search_tests<R: TestRepository>(
+    State(repo): State<R>,
+    owner: Owner,
+    params: Result<Query<SearchParams>, QueryRejection>,
+) -> Result<Json<Vec<SearchHit>>, AppError> {
+    let Query(params) = params?;
+    let limit = pagination::limit(params.limit)?;
+    let query = SearchQuery::parse(&params.q)
+        .ok_or_else(|| AppError::bad_request("q must contain at least one search term"))?;
+
+    let mut hits = repo.search(&owner, &query, limit).await?;
+    for hit in &mut hits {
+        hit.snippet = highlight(&hit.snippet);
+    }
+    Ok(Json(hits))
+}
+
+/// Escapes the snippet for HTML and turns the match markers into `<mark>`.
+fn highlight(snippet: &str) -> String {
+    let mut html = String::with_capacity(snippet.len());
+    for c in snippet.chars() {
+        match c {
+            START_SEL => html.push_str("<mark>"),
+            STOP_SEL => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
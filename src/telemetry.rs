@@ -0,0 +1,186 @@
+//! Where spans and logs go, and the spans SQL queries run in.
+//!
+//! Requests run in the span opened by [`crate::request_id::trace`], and
+//! the queries they make in child spans carrying the statement and the
+//! number of rows returned or affected.
+//!
+//! Under Shuttle the runtime installs the subscriber, so spans end up in
+//! Shuttle's logs. The standalone binary calls [`init`] instead, which
+//! exports spans to an OpenTelemetry collector over OTLP when one is
+//! configured and otherwise writes them to stdout as JSON, one line per
+//! event and per closed span.
+
+use std::future::Future;
+
+use serde::Serialize;
+use shuttle_runtime::tracing::{self, field::Empty, Instrument, Span};
+use sqlx::postgres::PgQueryResult;
+
+use crate::config::Settings;
+
+/// Service name reported to the collector unless `OTEL_SERVICE_NAME` is set.
+const DEFAULT_SERVICE_NAME: &str = "shuttle-axum";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TelemetryConfig {
+    /// gRPC endpoint of the OTLP collector, such as `http://localhost:4317`.
+    /// Spans are written to stdout as JSON without one.
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        TelemetryConfig {
+            otlp_endpoint: None,
+            service_name: DEFAULT_SERVICE_NAME.to_owned(),
+        }
+    }
+}
+
+impl TelemetryConfig {
+    /// Reads the standard `OTEL_EXPORTER_OTLP_ENDPOINT` and
+    /// `OTEL_SERVICE_NAME` settings.
+    pub(crate) fn read(settings: &mut Settings) -> Self {
+        let otlp_endpoint = settings
+            .get("OTEL_EXPORTER_OTLP_ENDPOINT")
+            .filter(|value| !value.is_empty());
+        if let Some(endpoint) = &otlp_endpoint {
+            settings.check(
+                endpoint.starts_with("http://") || endpoint.starts_with("https://"),
+                "OTEL_EXPORTER_OTLP_ENDPOINT must be an http(s) URL",
+            );
+        }
+        let service_name = settings
+            .get("OTEL_SERVICE_NAME")
+            .unwrap_or_else(|| DEFAULT_SERVICE_NAME.to_owned());
+        settings.check(
+            !service_name.is_empty(),
+            "OTEL_SERVICE_NAME must not be empty",
+        );
+        TelemetryConfig {
+            otlp_endpoint,
+            service_name,
+        }
+    }
+}
+
+/// Installs the global subscriber, filtered by `RUST_LOG` and defaulting to
+/// `info,sqlx=warn`. Call [`shutdown`] before exiting to flush spans.
+#[cfg(feature = "standalone")]
+pub fn init(config: &TelemetryConfig) -> Result<(), Box<dyn std::error::Error>> {
+    use opentelemetry::{
+        sdk::{trace, Resource},
+        KeyValue,
+    };
+    use opentelemetry_otlp::WithExportConfig;
+    use tracing_subscriber::{
+        fmt::{self, format::FmtSpan},
+        layer::SubscriberExt,
+        util::SubscriberInitExt,
+        EnvFilter,
+    };
+
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| "info,sqlx=warn".into());
+    let registry = tracing_subscriber::registry().with(filter);
+    match &config.otlp_endpoint {
+        Some(endpoint) => {
+            let tracer = opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(trace::config().with_resource(Resource::new([KeyValue::new(
+                    "service.name",
+                    config.service_name.clone(),
+                )])))
+                .install_batch(opentelemetry::runtime::Tokio)?;
+            registry
+                .with(tracing_opentelemetry::layer().with_tracer(tracer))
+                .with(fmt::layer())
+                .try_init()?;
+        }
+        None => registry
+            .with(
+                fmt::layer()
+                    .json()
+                    .with_current_span(true)
+                    .with_span_list(true)
+                    .with_span_events(FmtSpan::CLOSE),
+            )
+            .try_init()?,
+    }
+    Ok(())
+}
+
+/// Exports the spans still buffered for the collector, if any.
+#[cfg(feature = "standalone")]
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// Query results that know how many rows they hold or touched.
+pub(crate) trait Rows {
+    fn rows(&self) -> u64;
+}
+
+impl<T> Rows for Vec<T> {
+    fn rows(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+impl<T> Rows for Option<T> {
+    fn rows(&self) -> u64 {
+        self.is_some().into()
+    }
+}
+
+impl Rows for PgQueryResult {
+    fn rows(&self) -> u64 {
+        self.rows_affected()
+    }
+}
+
+fn sql_span(sql: &str) -> Span {
+    tracing::info_span!(
+        "sql",
+        otel.kind = "client",
+        db.system = "postgresql",
+        db.statement = sql,
+        db.rows = Empty,
+        error = Empty,
+    )
+}
+
+async fn trace_query<T>(
+    sql: &str,
+    query: impl Future<Output = Result<T, sqlx::Error>>,
+    rows: impl FnOnce(&T) -> u64,
+) -> Result<T, sqlx::Error> {
+    let span = sql_span(sql);
+    let result = query.instrument(span.clone()).await;
+    match &result {
+        Ok(output) => span.record("db.rows", rows(output)),
+        Err(error) => span.record("error", tracing::field::display(error)),
+    };
+    result
+}
+
+/// Awaits a query running `sql` in a span of its own.
+pub(crate) async fn traced<T: Rows>(
+    sql: &str,
+    query: impl Future<Output = Result<T, sqlx::Error>>,
+) -> Result<T, sqlx::Error> {
+    trace_query(sql, query, Rows::rows).await
+}
+
+/// [`traced`] for `fetch_one` queries, which return a single row.
+pub(crate) async fn traced_one<T>(
+    sql: &str,
+    query: impl Future<Output = Result<T, sqlx::Error>>,
+) -> Result<T, sqlx::Error> {
+    trace_query(sql, query, |_| 1).await
+}
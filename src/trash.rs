@@ -0,0 +1,152 @@
+//! Trashed rows: listing, restoring and purging them once they expire.
+
+use std::time::Duration;
+
+use axum::{
+    extract::{rejection::QueryRejection, Path, Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Serializer};
+use shuttle_runtime::tracing;
+use sqlx::FromRow;
+use tokio::task::JoinHandle;
+use utoipa::ToSchema;
+
+use crate::{
+    auth::Owner, config::Settings, error::AppError, pagination::Page, repository::TestRepository,
+    tagged, ListParams, Tagged, Test,
+};
+
+/// How often the purge job looks for expired rows.
+const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Longest `TRASH_RETENTION_DAYS` accepted, a century.
+const MAX_RETENTION_DAYS: i64 = 36_500;
+
+/// A row in the trash.
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct Trashed {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub test: Test,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// How long trashed rows are kept before the purge job removes them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PurgeConfig {
+    #[serde(rename = "retention_days", serialize_with = "retention_days")]
+    pub retention: chrono::Duration,
+}
+
+impl Default for PurgeConfig {
+    fn default() -> Self {
+        PurgeConfig {
+            retention: chrono::Duration::days(30),
+        }
+    }
+}
+
+impl PurgeConfig {
+    /// Reads `TRASH_RETENTION_DAYS`.
+    pub(crate) fn read(settings: &mut Settings) -> Self {
+        let default_days = PurgeConfig::default().retention.num_days();
+        let days = settings.parse("TRASH_RETENTION_DAYS", default_days);
+        if !(0..=MAX_RETENTION_DAYS).contains(&days) {
+            settings.invalid(format!(
+                "TRASH_RETENTION_DAYS must be between 0 and {MAX_RETENTION_DAYS}"
+            ));
+        }
+        PurgeConfig {
+            retention: chrono::Duration::days(days.clamp(0, MAX_RETENTION_DAYS)),
+        }
+    }
+}
+
+fn retention_days<S: Serializer>(
+    retention: &chrono::Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_i64(retention.num_days())
+}
+
+/// Removes rows trashed longer than the retention, returning how many.
+pub async fn purge<R: TestRepository>(repo: &R, config: PurgeConfig) -> Result<u64, AppError> {
+    // A retention reaching back before the earliest date keeps every row.
+    match Utc::now().checked_sub_signed(config.retention) {
+        Some(cutoff) => repo.purge(cutoff).await,
+        None => Ok(0),
+    }
+}
+
+/// Runs [`purge`] now and then every hour, for as long as the process lives.
+pub fn spawn_purge<R: TestRepository>(repo: R, config: PurgeConfig) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match purge(&repo, config).await {
+                Ok(0) => {}
+                Ok(purged) => tracing::info!(purged, "purged expired trash"),
+                Err(error) => tracing::warn!(?error, "trash purge failed"),
+            }
+        }
+    })
+}
+
+/// Lists the caller's trashed rows.
+#[utoipa::path(
+    get,
+    path = "/txt/trash",
+    tag = "txt",
+    params(ListParams),
+    responses(
+        (status = 200, description = "A page of trashed tests ordered by id", body = TrashPage),
+        (status = 400, description = "Invalid `limit` or `cursor`", body = ErrorBody),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn list_trash<R: TestRepository>(
+    State(repo): State<R>,
+    owner: Owner,
+    params: Result<Query<ListParams>, QueryRejection>,
+) -> Result<Json<Page<Trashed>>, AppError> {
+    let Query(params) = params?;
+    let (query, limit) = params.into_query()?;
+    let trashed = repo.trash(&owner, &query).await?;
+    Ok(Json(Page::from_overfetch(trashed, limit, |row| {
+        row.test.id
+    })))
+}
+
+/// Takes a row out of the trash, which counts as an update.
+#[utoipa::path(
+    post,
+    path = "/txt/{id}/restore",
+    tag = "txt",
+    params(("id" = i32, Path, description = "Test id")),
+    responses(
+        (status = 200, description = "The restored test", body = Test,
+            headers(
+                ("ETag" = String, description = "New version, for `If-Match`"),
+                ("Last-Modified" = String, description = "`updated_at` as an HTTP date"),
+            )),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+        (status = 403, description = "The API key is read-only", body = ErrorBody),
+        (status = 404, description = "No such test in the trash", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn restore_test<R: TestRepository>(
+    State(repo): State<R>,
+    owner: Owner,
+    Path(id): Path<i32>,
+) -> Result<Tagged, AppError> {
+    let test = repo
+        .restore(&owner, id)
+        .await?
+        .ok_or_else(|| AppError::not_found("test not found in the trash"))?;
+    Ok(tagged(test))
+}
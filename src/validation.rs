@@ -0,0 +1,97 @@
+use std::borrow::Cow;
+
+use axum::{
+    async_trait,
+    body::HttpBody,
+    extract::{FromRequest, Json},
+    http::Request,
+    BoxError,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::AppError;
+
+/// Maximum length of `test.txt` in characters, matching its `varchar(255)`
+/// column in `migrations/0001_create_test.sql`. Postgres counts characters,
+/// not bytes.
+pub const TXT_MAX_CHARS: usize = 255;
+
+/// A single invalid field in a request payload.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FieldError {
+    #[schema(value_type = String)]
+    pub field: Cow<'static, str>,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: impl Into<Cow<'static, str>>, message: impl Into<String>) -> Self {
+        FieldError {
+            field: field.into(),
+            message: message.into(),
+        }
+    }
+}
+
+/// Payloads that check and normalise themselves before reaching a handler.
+pub trait Validate: Sized {
+    fn validate(self) -> Result<Self, Vec<FieldError>>;
+}
+
+/// The `txt` of a `Test` being created or updated.
+///
+/// Sent as a bare JSON string, and trimmed during validation.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(transparent)]
+pub struct TestInput {
+    pub txt: String,
+}
+
+impl Validate for TestInput {
+    fn validate(self) -> Result<Self, Vec<FieldError>> {
+        let txt = validate_txt(&self.txt).map_err(|error| vec![error])?;
+        Ok(TestInput { txt })
+    }
+}
+
+/// Trims `txt` and checks it fits the `test.txt` column.
+pub fn validate_txt(txt: &str) -> Result<String, FieldError> {
+    let txt = txt.trim();
+    if txt.is_empty() {
+        return Err(FieldError::new("txt", "must not be empty"));
+    }
+    // Postgres text cannot hold NUL and would fail the insert.
+    if txt.contains('\0') {
+        return Err(FieldError::new("txt", "must not contain NUL characters"));
+    }
+    if txt.chars().count() > TXT_MAX_CHARS {
+        return Err(FieldError::new(
+            "txt",
+            format!("must be at most {TXT_MAX_CHARS} characters"),
+        ));
+    }
+    Ok(txt.to_owned())
+}
+
+/// JSON extractor that runs [`Validate`] and rejects with a 422 listing
+/// every invalid field.
+#[derive(Debug, Clone)]
+pub struct Valid<T>(pub T);
+
+#[async_trait]
+impl<S, B, T> FromRequest<S, B> for Valid<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request<B>, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(payload) = Json::<T>::from_request(req, state).await?;
+        payload.validate().map(Valid).map_err(AppError::Validation)
+    }
+}
@@ -0,0 +1,292 @@
+//! Two-way updates over the `GET /txt/ws` WebSocket.
+//!
+//! Clients send JSON command frames and get one `ack` or `error` frame back
+//! per command, echoing the command's `id`. After a `subscribe` command they
+//! also get a `change` frame for every write, delivered by [`events`].
+//!
+//! Commands are handled one at a time, and a subscription only reads the
+//! next change once the previous frame is written. A slow consumer thus lags
+//! in the changelog rather than in memory, and is sent a `reset` frame if it
+//! falls out of it. A consumer that takes no frame for [`SEND_TIMEOUT`] is
+//! disconnected.
+
+use std::{pin::Pin, time::Duration};
+
+use axum::{
+    extract::{
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        Extension, State,
+    },
+    response::Response,
+};
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use shuttle_runtime::tracing::{self, Instrument, Span};
+
+use crate::{
+    auth::{ApiKey, Owner, Scope},
+    error::{AppError, ErrorBody},
+    events::{self, ChangeKind, Notice},
+    rate_limit::Budget,
+    repository::TestRepository,
+    request_id::RequestId,
+    validation::{TestInput, Validate},
+    Test,
+};
+
+/// Largest command frame accepted.
+const MAX_FRAME_BYTES: usize = 64 * 1024;
+
+/// How long a client may leave a frame unread before it is disconnected.
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A command frame, tagged by `type`. Its `id` is read separately so that
+/// frames which fail to parse can still be answered.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Command {
+    /// Streams changes after `last_event_id`, or from now on.
+    Subscribe {
+        #[serde(default)]
+        last_event_id: Option<i64>,
+    },
+    Unsubscribe,
+    Create {
+        txt: TestInput,
+    },
+    Delete {
+        test_id: i32,
+    },
+}
+
+/// A frame sent to the client.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Frame {
+    /// The command `id` succeeded, with the row it created or deleted.
+    Ack {
+        id: Value,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        test: Option<Test>,
+    },
+    /// The command `id` failed, or `id` is `null` when it was unreadable.
+    Error { id: Value, error: ErrorBody },
+    Change {
+        event_id: i64,
+        kind: ChangeKind,
+        test: Test,
+    },
+    /// Changes were missed; fetch the tests again.
+    Reset { event_id: i64 },
+}
+
+impl Frame {
+    fn error(id: Value, error: AppError) -> Self {
+        let (_, error) = error.report();
+        Frame::Error { id, error }
+    }
+}
+
+impl From<Notice> for Frame {
+    fn from(notice: Notice) -> Self {
+        match notice {
+            Notice::Change(change) => Frame::Change {
+                event_id: change.id,
+                kind: change.kind,
+                test: change.test,
+            },
+            Notice::Reset { last } => Frame::Reset { event_id: last },
+        }
+    }
+}
+
+/// Reads the `id` and the command of a text frame.
+fn parse(text: &str) -> (Value, Result<Command, AppError>) {
+    let frame: Value = match serde_json::from_str(text) {
+        Ok(frame) => frame,
+        Err(error) => {
+            let error = AppError::bad_request(format!("frames must be JSON objects: {error}"));
+            return (Value::Null, Err(error));
+        }
+    };
+    let id = frame.get("id").cloned().unwrap_or(Value::Null);
+    let command = Command::deserialize(frame)
+        .map_err(|error| AppError::bad_request(format!("invalid command: {error}")));
+    (id, command)
+}
+
+type Subscription = Pin<Box<dyn Stream<Item = Notice> + Send>>;
+
+/// What the connection loop woke up for.
+enum Input {
+    Message(Option<Result<Message, axum::Error>>),
+    Notice(Option<Notice>),
+}
+
+/// A connected client, with the identity it authenticated the upgrade with.
+struct Session<R> {
+    repo: R,
+    owner: Owner,
+    api_key: Option<ApiKey>,
+    budget: Option<Budget>,
+    subscription: Option<Subscription>,
+}
+
+impl<R: TestRepository> Session<R> {
+    async fn run(mut self, mut socket: WebSocket) {
+        loop {
+            let input = tokio::select! {
+                message = socket.recv() => Input::Message(message),
+                notice = next_notice(&mut self.subscription) => Input::Notice(notice),
+            };
+            let frame = match input {
+                Input::Message(Some(Ok(Message::Text(text)))) => self.handle(&text).await,
+                Input::Message(Some(Ok(Message::Binary(_)))) => Frame::error(
+                    Value::Null,
+                    AppError::bad_request("frames must be JSON text"),
+                ),
+                Input::Message(Some(Ok(Message::Close(_))) | Some(Err(_)) | None) => return,
+                // Pings are answered by the socket itself.
+                Input::Message(Some(Ok(_))) => continue,
+                Input::Notice(Some(notice)) => notice.into(),
+                Input::Notice(None) => {
+                    // The changelog could not be read; the client resubscribes
+                    // from its last event id.
+                    let close = CloseFrame {
+                        code: close_code::ERROR,
+                        reason: "the subscription was interrupted".into(),
+                    };
+                    let _ = socket.send(Message::Close(Some(close))).await;
+                    return;
+                }
+            };
+            if !send(&mut socket, &frame).await {
+                return;
+            }
+        }
+    }
+
+    async fn handle(&mut self, text: &str) -> Frame {
+        let (id, command) = parse(text);
+        match self.execute(command).await {
+            Ok(test) => Frame::Ack { id, test },
+            Err(error) => Frame::error(id, error),
+        }
+    }
+
+    async fn execute(
+        &mut self,
+        command: Result<Command, AppError>,
+    ) -> Result<Option<Test>, AppError> {
+        match command? {
+            Command::Subscribe { last_event_id } => {
+                self.take(Scope::Read)?;
+                let notices =
+                    events::subscribe(self.repo.clone(), self.owner.clone(), last_event_id).await?;
+                self.subscription = Some(Box::pin(notices));
+                Ok(None)
+            }
+            Command::Unsubscribe => {
+                self.subscription = None;
+                Ok(None)
+            }
+            Command::Create { txt } => {
+                self.authorize_write()?;
+                self.take(Scope::ReadWrite)?;
+                let input = txt.validate().map_err(AppError::Validation)?;
+                crate::create(&self.repo, &self.owner, input)
+                    .await
+                    .map(Some)
+            }
+            Command::Delete { test_id } => {
+                self.authorize_write()?;
+                self.take(Scope::ReadWrite)?;
+                crate::delete(&self.repo, &self.owner, test_id)
+                    .await
+                    .map(Some)
+            }
+        }
+    }
+
+    /// Applies the checks [`crate::auth::authenticate`] makes for writes,
+    /// since the upgrade itself only needed to read.
+    fn authorize_write(&self) -> Result<(), AppError> {
+        match &self.api_key {
+            Some(key) if key.scope < Scope::ReadWrite => {
+                Err(AppError::Forbidden("the API key is not allowed to write"))
+            }
+            Some(_) => Ok(()),
+            None if self.owner.as_str().is_empty() => {
+                Err(AppError::Unauthorized("an API key is required"))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Charges a command to the client's rate limit, when there is one.
+    fn take(&self, scope: Scope) -> Result<(), AppError> {
+        match &self.budget {
+            Some(budget) => budget.take(scope),
+            None => Ok(()),
+        }
+    }
+}
+
+async fn next_notice(subscription: &mut Option<Subscription>) -> Option<Notice> {
+    match subscription {
+        Some(notices) => notices.next().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Writes `frame`, returning whether the connection is still usable.
+async fn send(socket: &mut WebSocket, frame: &Frame) -> bool {
+    let text = serde_json::to_string(frame).expect("frames serialize to JSON");
+    match tokio::time::timeout(SEND_TIMEOUT, socket.send(Message::Text(text))).await {
+        Ok(result) => result.is_ok(),
+        Err(_) => {
+            tracing::info!("disconnecting a WebSocket client that stopped reading");
+            false
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/txt/ws",
+    tag = "txt",
+    responses(
+        (status = 101, description = "Upgraded to a WebSocket of JSON text frames. Clients send \
+            `subscribe` (with an optional `last_event_id`), `unsubscribe`, `create` (with `txt`) \
+            and `delete` (with `test_id`) commands, each with an `id` echoed by the `ack` or \
+            `error` frame that answers it. Subscribers also get `change` frames with an \
+            `event_id`, `kind` and `test`, and a `reset` frame when changes were missed."),
+        (status = 400, description = "Not a WebSocket upgrade request", body = String,
+            content_type = "text/plain"),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorBody),
+    ),
+    security(("bearer" = []))
+)]
+pub(crate) async fn connect<R: TestRepository>(
+    State(repo): State<R>,
+    owner: Owner,
+    api_key: Option<Extension<ApiKey>>,
+    budget: Option<Extension<Budget>>,
+    Extension(request_id): Extension<RequestId>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    let session = Session {
+        repo,
+        owner,
+        api_key: api_key.map(|Extension(key)| key),
+        budget: budget.map(|Extension(budget)| budget),
+        subscription: None,
+    };
+    // The session stays part of the upgrade request, so its errors carry
+    // the same request id and its queries nest under the same span.
+    let span = Span::current();
+    upgrade
+        .max_message_size(MAX_FRAME_BYTES)
+        .on_upgrade(move |socket| request_id.scope(session.run(socket)).instrument(span))
+}
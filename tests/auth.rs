@@ -0,0 +1,148 @@
+mod common;
+
+use app::{auth::AuthConfig, RouterConfig};
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode},
+};
+use common::{TestApp, API_KEY, READ_KEY};
+use serde_json::json;
+
+fn bearer(key: &str) -> String {
+    format!("Bearer {key}")
+}
+
+#[tokio::test]
+async fn txt_routes_require_a_key() {
+    let app = TestApp::memory().await;
+    for (method, uri) in [
+        (Method::GET, "/txt"),
+        (Method::GET, "/txt/1"),
+        (Method::GET, "/txt/search?q=a"),
+        (Method::POST, "/txt"),
+        (Method::DELETE, "/txt/1"),
+    ] {
+        let response = app
+            .request(method.clone(), uri, &[("authorization", "")], None)
+            .await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED, "{method} {uri}");
+        assert_eq!(response.body["code"], "unauthorized");
+        assert_eq!(response.header("www-authenticate"), Some("Bearer"));
+    }
+}
+
+#[tokio::test]
+async fn unknown_keys_and_schemes_are_401() {
+    let app = TestApp::memory().await;
+    for authorization in [bearer("nope"), "Basic dXNlcjpwYXNz".to_owned()] {
+        let response = app
+            .request(
+                Method::GET,
+                "/txt",
+                &[("authorization", &authorization)],
+                None,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED, "{authorization}");
+    }
+}
+
+#[tokio::test]
+async fn read_keys_cannot_write() {
+    let app = TestApp::memory().await;
+    let read = bearer(READ_KEY);
+
+    let response = app
+        .request(Method::GET, "/txt", &[("authorization", &read)], None)
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let response = app
+        .request(
+            Method::POST,
+            "/txt",
+            &[("authorization", &read)],
+            Some(json!("a")),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+    assert_eq!(response.body["code"], "forbidden");
+}
+
+#[tokio::test]
+async fn read_write_keys_can_write() {
+    let app = TestApp::memory().await;
+    let response = app
+        .request(
+            Method::POST,
+            "/txt",
+            &[("authorization", &bearer(API_KEY))],
+            Some(json!("a")),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn public_reads_skip_the_key_for_gets_only() {
+    let config = RouterConfig {
+        auth: AuthConfig {
+            public_reads: true,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let app = TestApp::memory_with(config).await;
+
+    let response = app
+        .request(Method::GET, "/txt", &[("authorization", "")], None)
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    let response = app
+        .send(Request::get("/txt").body(Body::empty()).unwrap())
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let response = app
+        .send(
+            Request::post("/txt")
+                .header("content-type", "application/json")
+                .body(Body::from("\"a\""))
+                .unwrap(),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn health_routes_stay_public() {
+    let app = TestApp::memory().await;
+    for uri in ["/", "/healthz", "/readyz"] {
+        let response = app
+            .send(Request::get(uri).body(Body::empty()).unwrap())
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn keys_are_checked_against_the_database() {
+    let app = test_app!();
+    let response = app
+        .request(
+            Method::POST,
+            "/txt",
+            &[("authorization", &bearer(READ_KEY))],
+            Some(json!("a")),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::FORBIDDEN);
+
+    sqlx::query("UPDATE api_keys SET revoked_at = now()")
+        .execute(app.pool())
+        .await
+        .unwrap();
+    let response = app.get("/txt").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,146 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use serde_json::{json, Value};
+
+/// Rows that are not in the trash.
+async fn count(app: &common::TestApp) -> i64 {
+    sqlx::query_scalar("SELECT count(*) FROM test WHERE deleted_at IS NULL")
+        .fetch_one(app.pool())
+        .await
+        .unwrap()
+}
+
+fn ids(response: &Value) -> Vec<i64> {
+    response["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["id"].as_i64().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn bulk_create_inserts_in_order() {
+    let app = test_app!();
+    let response = app.post("/txt/bulk", json!(["a", " b ", "c"])).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let txts: Vec<_> = response.body["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["txt"].as_str().unwrap())
+        .collect();
+    assert_eq!(txts, ["a", "b", "c"]);
+    assert!(response.body.get("outcomes").is_none());
+}
+
+#[tokio::test]
+async fn bulk_create_atomic_rejects_the_whole_batch() {
+    let app = test_app!();
+    let response = app
+        .post("/txt/bulk?mode=atomic", json!(["a", "", "c"]))
+        .await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.body["errors"][0]["field"], "[1]");
+    assert_eq!(count(&app).await, 0);
+}
+
+#[tokio::test]
+async fn bulk_create_best_effort_reports_each_item() {
+    let app = test_app!();
+    let response = app
+        .post("/txt/bulk?mode=best_effort", json!(["a", "", "c"]))
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let ids = ids(&response.body);
+    assert_eq!(ids.len(), 2);
+
+    let outcomes = &response.body["outcomes"];
+    assert_eq!(
+        outcomes[0],
+        json!({ "status": "created", "index": 0, "id": ids[0] })
+    );
+    assert_eq!(outcomes[1]["status"], "invalid");
+    assert_eq!(outcomes[1]["index"], 1);
+    assert_eq!(
+        outcomes[2],
+        json!({ "status": "created", "index": 2, "id": ids[1] })
+    );
+}
+
+#[tokio::test]
+async fn bulk_create_rejects_oversized_batches() {
+    let app = test_app!();
+    let batch = vec!["x"; 1001];
+    let response = app.post("/txt/bulk", json!(batch)).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn bulk_create_rejects_unknown_modes() {
+    let app = test_app!();
+    let response = app.post("/txt/bulk?mode=yolo", json!(["a"])).await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn bulk_delete_removes_all_rows() {
+    let app = test_app!();
+    let created = app.post("/txt/bulk", json!(["a", "b", "c"])).await;
+    let created = ids(&created.body);
+
+    let response = app
+        .request(
+            Method::DELETE,
+            "/txt/bulk",
+            &[],
+            Some(json!([created[0], created[2]])),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(ids(&response.body), [created[0], created[2]]);
+    assert_eq!(count(&app).await, 1);
+}
+
+#[tokio::test]
+async fn bulk_delete_atomic_rolls_back_on_missing_ids() {
+    let app = test_app!();
+    let created = app.create("a").await;
+
+    let response = app
+        .request(
+            Method::DELETE,
+            "/txt/bulk?mode=atomic",
+            &[],
+            Some(json!([created["id"], 4242])),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert_eq!(response.body["message"], "tests not found: 4242");
+    assert_eq!(count(&app).await, 1);
+}
+
+#[tokio::test]
+async fn bulk_delete_best_effort_reports_missing_ids() {
+    let app = test_app!();
+    let created = app.create("a").await;
+
+    let response = app
+        .request(
+            Method::DELETE,
+            "/txt/bulk?mode=best_effort",
+            &[],
+            Some(json!([created["id"], 4242])),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(
+        response.body["outcomes"],
+        json!([
+            { "status": "deleted", "index": 0, "id": created["id"] },
+            { "status": "not_found", "index": 1, "id": 4242 },
+        ])
+    );
+    assert_eq!(count(&app).await, 0);
+}
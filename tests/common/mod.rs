@@ -0,0 +1,461 @@
+//! Test harness that drives `router()` in-process against Postgres.
+//!
+//! Tests use `TEST_DATABASE_URL` when it is set. Otherwise a throwaway
+//! server is started from `initdb`/`postgres` on the `PATH`, listening on
+//! localhost only, and removed when the test binary exits. Every test gets
+//! its own schema with the migrations applied, so tests can run in parallel.
+//!
+//! When neither is available the database tests fail, unless
+//! `SKIP_DB_TESTS=1` skips them explicitly.
+//! [`TestApp::memory`] builds the router over an in-memory repository instead,
+//! for handler tests that do not need a database.
+
+#![allow(dead_code)]
+
+use std::{
+    env,
+    net::{TcpListener, TcpStream},
+    path::Path,
+    process::{Child, Command, Stdio},
+    sync::{Mutex, OnceLock},
+    thread,
+    time::Duration,
+};
+
+use app::{
+    auth::{self, Scope},
+    repository::{MemoryRepository, PgRepository},
+    RouterConfig,
+};
+use axum::{
+    body::{Body, BoxBody, HttpBody},
+    http::{header, HeaderMap, Method, Request, StatusCode},
+    response::Response,
+    Router,
+};
+use serde_json::Value;
+use sqlx::{postgres::PgPoolOptions, Executor, PgPool};
+use tower::ServiceExt;
+use uuid::Uuid;
+
+/// Read-write key seeded into every app and sent unless a test overrides
+/// the `Authorization` header.
+pub const API_KEY: &str = "test-read-write-key";
+/// Read-only key seeded into every app.
+pub const READ_KEY: &str = "test-read-key";
+
+/// A router bound to a fresh schema, or to an in-memory repository.
+pub struct TestApp {
+    pub router: Router,
+    pub pool: Option<PgPool>,
+}
+
+/// A buffered response with its body parsed as JSON (`Null` when empty).
+#[derive(Debug)]
+pub struct TestResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Value,
+}
+
+impl TestResponse {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+}
+
+impl TestApp {
+    /// A router over a [`MemoryRepository`], which needs no database.
+    pub async fn memory() -> Self {
+        Self::memory_with(RouterConfig::default()).await
+    }
+
+    pub async fn memory_with(config: RouterConfig) -> Self {
+        let repo = MemoryRepository::new()
+            .with_api_key("tests", API_KEY, Scope::ReadWrite)
+            .with_api_key("tests-read", READ_KEY, Scope::Read);
+        TestApp {
+            router: app::router(repo, config).await,
+            pool: None,
+        }
+    }
+
+    /// Sets up a migrated schema, or returns `None` under `SKIP_DB_TESTS=1`
+    /// so the calling test can skip itself.
+    pub async fn spawn() -> Option<Self> {
+        Self::spawn_with(RouterConfig::default()).await
+    }
+
+    pub async fn spawn_with(config: RouterConfig) -> Option<Self> {
+        let url = database_url()?;
+        let schema = format!("test_{}", Uuid::new_v4().simple());
+
+        let admin = connect(url, 1).await;
+        admin
+            .execute(format!("CREATE SCHEMA {schema}").as_str())
+            .await
+            .expect("failed to create test schema");
+        admin.close().await;
+
+        let search_path = schema.clone();
+        let pool = PgPoolOptions::new()
+            .max_connections(5)
+            .after_connect(move |conn, _| {
+                let statement = format!("SET search_path TO {search_path}");
+                Box::pin(async move {
+                    conn.execute(statement.as_str()).await?;
+                    Ok(())
+                })
+            })
+            .connect(url)
+            .await
+            .expect("failed to connect to test schema");
+        app::migrate::run(&pool)
+            .await
+            .expect("failed to apply migrations");
+
+        for (name, key, scope) in [
+            ("tests", API_KEY, "read_write"),
+            ("tests-read", READ_KEY, "read"),
+        ] {
+            sqlx::query("INSERT INTO api_keys (name, key_hash, scope) VALUES ($1, $2, $3)")
+                .bind(name)
+                .bind(auth::hash_key(key))
+                .bind(scope)
+                .execute(&pool)
+                .await
+                .expect("failed to seed API keys");
+        }
+
+        let repo = PgRepository::new(pool.clone());
+        repo.spawn_listener()
+            .await
+            .expect("failed to listen for changes");
+        let router = app::router(repo, config).await;
+        Some(TestApp {
+            router,
+            pool: Some(pool),
+        })
+    }
+
+    /// Another instance over the same schema, with the default config and
+    /// no change listener of its own.
+    pub async fn replica(&self) -> TestApp {
+        let pool = self.pool().clone();
+        let router = app::router(PgRepository::new(pool.clone()), RouterConfig::default()).await;
+        TestApp {
+            router,
+            pool: Some(pool),
+        }
+    }
+
+    /// The pool of a Postgres-backed app.
+    pub fn pool(&self) -> &PgPool {
+        self.pool.as_ref().expect("app is not backed by Postgres")
+    }
+
+    /// Sends `request` without reading the body, for streaming responses.
+    pub async fn open(&self, request: Request<Body>) -> Response {
+        self.router
+            .clone()
+            .oneshot(request)
+            .await
+            .expect("router is infallible")
+    }
+
+    pub async fn send(&self, request: Request<Body>) -> TestResponse {
+        let response = self.open(request).await;
+        let status = response.status();
+        let headers = response.headers().clone();
+        let bytes = hyper::body::to_bytes(response.into_body())
+            .await
+            .expect("failed to read response body");
+        let body = if bytes.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&bytes)
+                .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&bytes).into_owned()))
+        };
+        TestResponse {
+            status,
+            headers,
+            body,
+        }
+    }
+
+    pub async fn request(
+        &self,
+        method: Method,
+        uri: &str,
+        headers: &[(&str, &str)],
+        body: Option<Value>,
+    ) -> TestResponse {
+        self.send(request(method, uri, headers, body)).await
+    }
+
+    pub async fn get(&self, uri: &str) -> TestResponse {
+        self.request(Method::GET, uri, &[], None).await
+    }
+
+    pub async fn post(&self, uri: &str, body: Value) -> TestResponse {
+        self.request(Method::POST, uri, &[], Some(body)).await
+    }
+
+    pub async fn delete(&self, uri: &str) -> TestResponse {
+        self.request(Method::DELETE, uri, &[], None).await
+    }
+
+    /// Serves the router on an ephemeral localhost port, for clients that
+    /// need a real connection such as WebSockets.
+    pub async fn serve(&self) -> std::net::SocketAddr {
+        let server = axum::Server::bind(&([127, 0, 0, 1], 0).into())
+            .serve(self.router.clone().into_make_service());
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        addr
+    }
+
+    /// Opens `GET /txt/events` with extra `headers`.
+    pub async fn events(&self, headers: &[(&str, &str)]) -> EventStream {
+        let response = self
+            .open(request(Method::GET, "/txt/events", headers, None))
+            .await;
+        EventStream::new(response)
+    }
+
+    /// Creates a row through the API and returns its JSON.
+    pub async fn create(&self, txt: &str) -> Value {
+        let response = self.post("/txt", Value::from(txt)).await;
+        assert_eq!(response.status, StatusCode::OK, "{:?}", response.body);
+        response.body
+    }
+}
+
+/// One server-sent event.
+#[derive(Debug)]
+pub struct SseEvent {
+    pub id: Option<String>,
+    pub event: String,
+    /// `data` parsed as JSON, or as a string when it is not JSON.
+    pub data: Value,
+}
+
+/// Reads events off a `text/event-stream` response as they arrive.
+pub struct EventStream {
+    body: BoxBody,
+    buffer: String,
+}
+
+impl EventStream {
+    pub fn new(response: Response) -> Self {
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok()),
+            Some("text/event-stream")
+        );
+        EventStream {
+            body: response.into_body(),
+            buffer: String::new(),
+        }
+    }
+
+    /// The next event, skipping keep-alive comments. Panics when none
+    /// arrives within a few seconds.
+    pub async fn next(&mut self) -> SseEvent {
+        loop {
+            if let Some(end) = self.buffer.find("\n\n") {
+                let block: String = self.buffer.drain(..end + 2).collect();
+                if let Some(event) = parse_event(&block) {
+                    return event;
+                }
+                continue;
+            }
+            let chunk = tokio::time::timeout(Duration::from_secs(5), self.body.data())
+                .await
+                .expect("timed out waiting for an event")
+                .expect("event stream ended")
+                .expect("failed to read event stream");
+            self.buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+    }
+
+    /// Asserts that no event arrives for a moment.
+    pub async fn assert_idle(&mut self) {
+        let next = tokio::time::timeout(Duration::from_millis(200), self.next()).await;
+        assert!(next.is_err(), "unexpected event {next:?}");
+    }
+}
+
+fn parse_event(block: &str) -> Option<SseEvent> {
+    let (mut id, mut event, mut data) = (None, String::from("message"), None);
+    for line in block.lines() {
+        let (field, value) = line.split_once(':').unwrap_or((line, ""));
+        let value = value.strip_prefix(' ').unwrap_or(value);
+        match field {
+            "id" => id = Some(value.to_owned()),
+            "event" => event = value.to_owned(),
+            "data" => data = Some(value.to_owned()),
+            _ => {}
+        }
+    }
+    let data = data?;
+    Some(SseEvent {
+        id,
+        event,
+        data: serde_json::from_str(&data).unwrap_or(Value::String(data)),
+    })
+}
+
+/// Builds a request sent with [`API_KEY`] unless `headers` has an
+/// `Authorization` of its own.
+pub fn request(
+    method: Method,
+    uri: &str,
+    headers: &[(&str, &str)],
+    body: Option<Value>,
+) -> Request<Body> {
+    let mut builder = Request::builder().method(method).uri(uri);
+    if !headers
+        .iter()
+        .any(|(name, _)| name.eq_ignore_ascii_case("authorization"))
+    {
+        builder = builder.header(header::AUTHORIZATION, format!("Bearer {API_KEY}"));
+    }
+    for (name, value) in headers {
+        builder = builder.header(*name, *value);
+    }
+    let body = match body {
+        Some(body) => {
+            builder = builder.header(header::CONTENT_TYPE, "application/json");
+            Body::from(body.to_string())
+        }
+        None => Body::empty(),
+    };
+    builder.body(body).unwrap()
+}
+
+async fn connect(url: &str, max_connections: u32) -> PgPool {
+    // A freshly started server may still be refusing connections.
+    let mut attempts = 0;
+    loop {
+        match PgPoolOptions::new()
+            .max_connections(max_connections)
+            .connect(url)
+            .await
+        {
+            Ok(pool) => return pool,
+            Err(_) if attempts < 50 => {
+                attempts += 1;
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            Err(error) => panic!("failed to connect to {url}: {error}"),
+        }
+    }
+}
+
+fn database_url() -> Option<&'static str> {
+    static URL: OnceLock<Option<String>> = OnceLock::new();
+    URL.get_or_init(|| {
+        if env::var("SKIP_DB_TESTS").is_ok_and(|value| value == "1") {
+            eprintln!("skipping database tests: SKIP_DB_TESTS=1");
+            return None;
+        }
+        if let Ok(url) = env::var("TEST_DATABASE_URL") {
+            return Some(url);
+        }
+        match EphemeralPostgres::start() {
+            Ok(url) => Some(url),
+            Err(error) => panic!(
+                "no database for the tests: set TEST_DATABASE_URL, put initdb/postgres on \
+                 the PATH, or set SKIP_DB_TESTS=1 to skip them ({error})"
+            ),
+        }
+    })
+    .as_deref()
+}
+
+/// A Postgres server that lives as long as the test binary.
+struct EphemeralPostgres;
+
+impl EphemeralPostgres {
+    fn start() -> Result<String, String> {
+        let dir = env::temp_dir().join(format!("shuttle-axum-test-{}", Uuid::new_v4().simple()));
+        let data = dir.join("data");
+
+        let output = Command::new("initdb")
+            .arg("-D")
+            .arg(&data)
+            .args(["-U", "postgres", "--auth=trust", "--no-sync", "-E", "UTF8"])
+            .output()
+            .map_err(|error| format!("initdb: {error}"))?;
+        if !output.status.success() {
+            let _ = std::fs::remove_dir_all(&dir);
+            return Err(format!(
+                "initdb: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let port = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .map_err(|error| format!("no free port: {error}"))?
+            .port();
+        let child = Self::spawn_server(&dir, &data, port)?;
+        Self::keep_alive(child);
+
+        for _ in 0..100 {
+            if TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                return Ok(format!("postgres://postgres@127.0.0.1:{port}/postgres"));
+            }
+            thread::sleep(Duration::from_millis(100));
+        }
+        Err("postgres did not start listening".to_owned())
+    }
+
+    /// Runs `postgres` under a shell that stops it and removes its data once
+    /// our end of its stdin closes, which happens when the test binary exits.
+    fn spawn_server(dir: &Path, data: &Path, port: u16) -> Result<Child, String> {
+        let script = r#"postgres -D "$1" -p "$2" -k "$3" -h 127.0.0.1 -F >"$3/postgres.log" 2>&1 &
+pid=$!
+cat >/dev/null
+kill -INT "$pid"
+wait "$pid"
+rm -rf "$3""#;
+        Command::new("sh")
+            .args(["-c", script, "sh"])
+            .arg(data)
+            .arg(port.to_string())
+            .arg(dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|error| format!("postgres: {error}"))
+    }
+
+    /// Holds on to the child, and with it the write end of its stdin.
+    fn keep_alive(child: Child) {
+        static SERVER: OnceLock<Mutex<Child>> = OnceLock::new();
+        let _ = SERVER.set(Mutex::new(child));
+    }
+}
+
+/// Skips the test when `SKIP_DB_TESTS=1`.
+#[macro_export]
+macro_rules! test_app {
+    () => {
+        match common::TestApp::spawn().await {
+            Some(app) => app,
+            None => return,
+        }
+    };
+    ($config:expr) => {
+        match common::TestApp::spawn_with($config).await {
+            Some(app) => app,
+            None => return,
+        }
+    };
+}
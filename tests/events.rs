@@ -0,0 +1,163 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use common::TestApp;
+use serde_json::{json, Value};
+
+async fn update(app: &TestApp, id: &Value, txt: &str) {
+    let response = app
+        .request(
+            Method::PUT,
+            &format!("/txt/{id}"),
+            &[("if-match", "*")],
+            Some(json!(txt)),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{:?}", response.body);
+}
+
+fn event_id(event: &common::SseEvent) -> i64 {
+    event.id.as_deref().unwrap().parse().unwrap()
+}
+
+async fn check_writes_are_streamed_as_they_happen(app: TestApp) {
+    let mut events = app.events(&[]).await;
+
+    let test = app.create("first").await;
+    let id = &test["id"];
+    update(&app, id, "second").await;
+    assert_eq!(
+        app.delete(&format!("/txt/{id}")).await.status,
+        StatusCode::OK
+    );
+    let response = app
+        .request(Method::POST, &format!("/txt/{id}/restore"), &[], None)
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app.post("/txt/bulk", json!(["third"])).await;
+    assert_eq!(response.status, StatusCode::OK, "{:?}", response.body);
+
+    let mut last = 0;
+    for (kind, txt, version) in [
+        ("created", "first", 1),
+        ("updated", "second", 2),
+        ("deleted", "second", 2),
+        ("created", "second", 3),
+        ("created", "third", 1),
+    ] {
+        let event = events.next().await;
+        assert_eq!(event.event, kind);
+        assert_eq!(event.data["txt"], txt);
+        assert_eq!(event.data["version"], version);
+        assert!(event.data["updated_at"].is_string());
+        assert!(event_id(&event) > last, "event ids must increase");
+        last = event_id(&event);
+    }
+    events.assert_idle().await;
+}
+
+#[tokio::test]
+async fn writes_are_streamed_as_they_happen() {
+    check_writes_are_streamed_as_they_happen(test_app!()).await;
+}
+
+#[tokio::test]
+async fn writes_are_streamed_as_they_happen_in_memory() {
+    check_writes_are_streamed_as_they_happen(TestApp::memory().await).await;
+}
+
+async fn check_last_event_id_replays_missed_events(app: TestApp) {
+    let mut events = app.events(&[]).await;
+    app.create("seen").await;
+    let seen = events.next().await;
+    drop(events);
+
+    let missed = app.create("missed").await;
+    update(&app, &missed["id"], "missed twice").await;
+
+    let last_event_id = seen.id.unwrap();
+    let mut events = app.events(&[("last-event-id", &last_event_id)]).await;
+    let event = events.next().await;
+    assert_eq!(
+        (event.event.as_str(), &event.data["txt"]),
+        ("created", &json!("missed"))
+    );
+    let event = events.next().await;
+    assert_eq!(
+        (event.event.as_str(), &event.data["txt"]),
+        ("updated", &json!("missed twice"))
+    );
+
+    // Live events follow the replayed ones.
+    app.create("live").await;
+    assert_eq!(events.next().await.data["txt"], "live");
+}
+
+#[tokio::test]
+async fn last_event_id_replays_missed_events() {
+    check_last_event_id_replays_missed_events(test_app!()).await;
+}
+
+#[tokio::test]
+async fn last_event_id_replays_missed_events_in_memory() {
+    check_last_event_id_replays_missed_events(TestApp::memory().await).await;
+}
+
+#[tokio::test]
+async fn writes_through_other_instances_are_streamed() {
+    let app = test_app!();
+    let other = app.replica().await;
+    let mut events = app.events(&[]).await;
+    // Let the stream catch up and wait for notifications.
+    events.assert_idle().await;
+
+    other.create("elsewhere").await;
+    let event = events.next().await;
+    assert_eq!(event.event, "created");
+    assert_eq!(event.data["txt"], "elsewhere");
+}
+
+#[tokio::test]
+async fn unknown_last_event_id_resets_the_client() {
+    let app = TestApp::memory().await;
+    app.create("existing").await;
+
+    let mut events = app.events(&[("last-event-id", "42")]).await;
+    let event = events.next().await;
+    assert_eq!(event.event, "reset");
+    assert_eq!(event.id.as_deref(), Some("1"));
+
+    app.create("new").await;
+    let event = events.next().await;
+    assert_eq!(event.event, "created");
+    assert_eq!(event.id.as_deref(), Some("2"));
+}
+
+#[tokio::test]
+async fn invalid_last_event_id_is_rejected() {
+    let app = TestApp::memory().await;
+    let response = app
+        .request(
+            Method::GET,
+            "/txt/events",
+            &[("last-event-id", "latest")],
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+    assert_eq!(response.body["code"], "bad_request");
+}
+
+#[tokio::test]
+async fn events_require_authentication() {
+    let app = TestApp::memory().await;
+    let response = app
+        .request(
+            Method::GET,
+            "/txt/events",
+            &[("authorization", "Bearer nope")],
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
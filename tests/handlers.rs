@@ -0,0 +1,181 @@
+//! Handler tests against the in-memory repository; no database required.
+
+mod common;
+
+use axum::http::{Method, StatusCode};
+use common::TestApp;
+use serde_json::{json, Value};
+
+fn txts(body: &Value) -> Vec<&str> {
+    body.as_array()
+        .unwrap_or_else(|| body["items"].as_array().unwrap())
+        .iter()
+        .map(|item| item["txt"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn crud_round_trip() {
+    let app = TestApp::memory().await;
+
+    let created = app.create(" hello ").await;
+    assert_eq!(created["id"], 1);
+    assert_eq!(created["txt"], "hello");
+    assert_eq!(created["version"], 1);
+    assert_eq!(created["created_at"], created["updated_at"]);
+
+    let response = app.get("/txt/1").await;
+    assert_eq!(response.body, created);
+    assert_eq!(response.header("etag"), Some("\"1\""));
+
+    let response = app
+        .request(
+            Method::PUT,
+            "/txt/1",
+            &[("if-match", "\"1\"")],
+            Some(json!("bye")),
+        )
+        .await;
+    assert_eq!(response.body["txt"], "bye");
+    assert_eq!(response.body["version"], 2);
+    assert_eq!(response.body["created_at"], created["created_at"]);
+
+    let response = app.delete("/txt/1").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(app.get("/txt/1").await.status, StatusCode::NOT_FOUND);
+    assert_eq!(app.delete("/txt/1").await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn create_validates_txt() {
+    let app = TestApp::memory().await;
+    let response = app.post("/txt", json!("x".repeat(256))).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(
+        response.body["errors"],
+        json!([{ "field": "txt", "message": "must be at most 255 characters" }])
+    );
+}
+
+#[tokio::test]
+async fn update_checks_versions() {
+    let app = TestApp::memory().await;
+    app.create("hello").await;
+
+    let stale = app
+        .request(
+            Method::PATCH,
+            "/txt/1",
+            &[("if-match", "\"2\"")],
+            Some(json!("a")),
+        )
+        .await;
+    assert_eq!(stale.status, StatusCode::PRECONDITION_FAILED);
+
+    let weak = app
+        .request(
+            Method::PATCH,
+            "/txt/1",
+            &[("if-match", "W/\"1\"")],
+            Some(json!("a")),
+        )
+        .await;
+    assert_eq!(weak.status, StatusCode::PRECONDITION_FAILED);
+
+    let missing = app
+        .request(
+            Method::PATCH,
+            "/txt/2",
+            &[("if-match", "*")],
+            Some(json!("a")),
+        )
+        .await;
+    assert_eq!(missing.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn list_pages_and_filters() {
+    let app = TestApp::memory().await;
+    app.post(
+        "/txt/bulk",
+        json!(["apple", "banana", "pineapple", "cherry"]),
+    )
+    .await;
+
+    let first = app.get("/txt?limit=3&order=desc").await;
+    assert_eq!(txts(&first.body), ["cherry", "pineapple", "banana"]);
+    let cursor = first.body["next_cursor"].as_str().unwrap();
+    let second = app
+        .get(&format!("/txt?limit=3&order=desc&cursor={cursor}"))
+        .await;
+    assert_eq!(txts(&second.body), ["apple"]);
+    assert_eq!(second.body["next_cursor"], Value::Null);
+
+    let filtered = app.get("/txt?q=APPLE").await;
+    assert_eq!(txts(&filtered.body), ["apple", "pineapple"]);
+}
+
+#[tokio::test]
+async fn bulk_modes() {
+    let app = TestApp::memory().await;
+
+    let atomic = app.post("/txt/bulk", json!(["a", ""])).await;
+    assert_eq!(atomic.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(app.get("/txt").await.body["items"], json!([]));
+
+    let best_effort = app
+        .post("/txt/bulk?mode=best_effort", json!(["a", "", "b"]))
+        .await;
+    assert_eq!(txts(&best_effort.body), ["a", "b"]);
+
+    let delete = app
+        .request(Method::DELETE, "/txt/bulk", &[], Some(json!([1, 3])))
+        .await;
+    assert_eq!(delete.status, StatusCode::NOT_FOUND);
+    assert_eq!(delete.body["message"], "tests not found: 3");
+
+    let delete = app
+        .request(
+            Method::DELETE,
+            "/txt/bulk?mode=best_effort",
+            &[],
+            Some(json!([1, 3])),
+        )
+        .await;
+    assert_eq!(txts(&delete.body), ["a"]);
+    assert_eq!(delete.body["outcomes"][1]["status"], "not_found");
+}
+
+#[tokio::test]
+async fn search_matches_terms_prefixes_and_phrases() {
+    let app = TestApp::memory().await;
+    app.post(
+        "/txt/bulk",
+        json!(["shuttle rocks", "rocks <b>shuttle</b>", "axum"]),
+    )
+    .await;
+
+    let response = app.get("/txt/search?q=%22shuttle%20rocks%22").await;
+    assert_eq!(txts(&response.body), ["shuttle rocks"]);
+    assert_eq!(
+        response.body[0]["snippet"],
+        "<mark>shuttle</mark> <mark>rocks</mark>"
+    );
+
+    let response = app.get("/txt/search?q=shut*").await;
+    assert_eq!(
+        response.body[1]["snippet"],
+        "rocks &lt;b&gt;<mark>shuttle</mark>&lt;/b&gt;"
+    );
+
+    let response = app.get("/txt/search?q=ax*").await;
+    assert_eq!(txts(&response.body), ["axum"]);
+}
+
+#[tokio::test]
+async fn readyz_without_a_pool() {
+    let app = TestApp::memory().await;
+    let response = app.get("/readyz").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert!(response.body.get("pool").is_none());
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use app::repository::PgRepository;
+use axum::http::StatusCode;
+use sqlx::postgres::PgPoolOptions;
+
+#[tokio::test]
+async fn healthz_is_always_ok() {
+    let app = test_app!();
+    let response = app.get("/healthz").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body["status"], "ok");
+}
+
+#[tokio::test]
+async fn readyz_reports_checks_and_pool_stats() {
+    let app = test_app!();
+    let response = app.get("/readyz").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body["checks"]["database"]["status"], "ok");
+    assert_eq!(response.body["checks"]["schema"]["status"], "ok");
+    assert!(response.body["pool"]["size"].as_u64().unwrap() >= 1);
+}
+
+#[tokio::test]
+async fn readyz_fails_without_the_test_table() {
+    let app = test_app!();
+    sqlx::query("DROP TABLE test")
+        .execute(app.pool())
+        .await
+        .unwrap();
+
+    let response = app.get("/readyz").await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.body["status"], "unavailable");
+    assert_eq!(response.body["checks"]["database"]["status"], "ok");
+    assert_eq!(response.body["checks"]["schema"]["status"], "unavailable");
+}
+
+#[tokio::test]
+async fn readyz_fails_when_the_database_is_unreachable() {
+    // Port 1 is never a Postgres server, so every check fails fast.
+    let pool = PgPoolOptions::new()
+        .acquire_timeout(std::time::Duration::from_millis(500))
+        .connect_lazy("postgres://postgres@127.0.0.1:1/postgres")
+        .unwrap();
+    let app = common::TestApp {
+        router: app::router(PgRepository::new(pool.clone()), Default::default()).await,
+        pool: Some(pool),
+    };
+
+    let response = app.get("/readyz").await;
+    assert_eq!(response.status, StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.body["checks"]["database"]["status"], "unavailable");
+}
@@ -0,0 +1,200 @@
+mod common;
+
+use std::{io::Write, time::Duration};
+
+use app::{
+    layers::{CorsOrigins, LayerConfig},
+    RouterConfig,
+};
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderValue, Method, Request, StatusCode},
+};
+use common::TestApp;
+use flate2::{write::GzEncoder, Compression};
+use sqlx::Executor;
+
+const ORIGIN: &str = "https://app.example";
+
+fn config(layers: LayerConfig) -> RouterConfig {
+    RouterConfig {
+        layers,
+        ..Default::default()
+    }
+}
+
+/// A `POST /txt` with a raw JSON body and extra `headers`.
+fn post_txt(body: impl Into<Body>, headers: &[(&str, &str)]) -> Request<Body> {
+    let mut request = common::request(Method::POST, "/txt", headers, None).map(|_| body.into());
+    request.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    request
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data).unwrap();
+    encoder.finish().unwrap()
+}
+
+async fn preflight(app: &TestApp, origin: &str) -> common::TestResponse {
+    let headers = [
+        ("authorization", ""),
+        ("origin", origin),
+        ("access-control-request-method", "PUT"),
+        ("access-control-request-headers", "authorization,if-match"),
+    ];
+    app.request(Method::OPTIONS, "/txt", &headers, None).await
+}
+
+#[tokio::test]
+async fn cors_preflights_are_answered_for_allowed_origins() {
+    let app = TestApp::memory_with(config(LayerConfig {
+        cors: Some(CorsOrigins::List(vec![HeaderValue::from_static(ORIGIN)])),
+        ..Default::default()
+    }))
+    .await;
+
+    let response = preflight(&app, ORIGIN).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("access-control-allow-origin"), Some(ORIGIN));
+    let methods = response.header("access-control-allow-methods").unwrap();
+    assert!(methods.contains("PUT"), "{methods}");
+    let headers = response.header("access-control-allow-headers").unwrap();
+    assert!(headers.contains("if-match"), "{headers}");
+
+    let response = preflight(&app, "https://evil.example").await;
+    assert_eq!(response.header("access-control-allow-origin"), None);
+
+    let response = app
+        .request(Method::GET, "/txt", &[("origin", ORIGIN)], None)
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("access-control-allow-origin"), Some(ORIGIN));
+    let exposed = response.header("access-control-expose-headers").unwrap();
+    assert!(exposed.contains("x-request-id"), "{exposed}");
+}
+
+#[tokio::test]
+async fn cors_is_off_by_default() {
+    let app = TestApp::memory().await;
+    let response = app
+        .request(Method::GET, "/txt", &[("origin", ORIGIN)], None)
+        .await;
+    assert_eq!(response.header("access-control-allow-origin"), None);
+}
+
+#[tokio::test]
+async fn responses_are_compressed_when_accepted() {
+    let app = TestApp::memory().await;
+    for encoding in ["gzip", "br", "zstd"] {
+        let response = app
+            .request(
+                Method::GET,
+                "/openapi.json",
+                &[("accept-encoding", encoding)],
+                None,
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.header("content-encoding"), Some(encoding));
+    }
+
+    let app = TestApp::memory_with(config(LayerConfig {
+        compression: false,
+        ..Default::default()
+    }))
+    .await;
+    let response = app
+        .request(
+            Method::GET,
+            "/openapi.json",
+            &[("accept-encoding", "gzip")],
+            None,
+        )
+        .await;
+    assert_eq!(response.header("content-encoding"), None);
+}
+
+#[tokio::test]
+async fn compressed_request_bodies_are_decompressed() {
+    let app = TestApp::memory().await;
+    let body = gzip(br#""zipped""#);
+    let response = app
+        .send(post_txt(body.clone(), &[("content-encoding", "gzip")]))
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{:?}", response.body);
+    assert_eq!(response.body["txt"], "zipped");
+
+    let app = TestApp::memory_with(config(LayerConfig {
+        decompression: false,
+        ..Default::default()
+    }))
+    .await;
+    let response = app
+        .send(post_txt(body, &[("content-encoding", "gzip")]))
+        .await;
+    assert_eq!(response.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn oversized_bodies_are_rejected() {
+    let app = TestApp::memory_with(config(LayerConfig {
+        max_body_bytes: 64,
+        ..Default::default()
+    }))
+    .await;
+    let response = app.send(post_txt(r#""small""#, &[])).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let large = format!("{:?}", "a".repeat(100));
+    let response = app.send(post_txt(large.clone(), &[])).await;
+    assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(response.body["code"], "payload_too_large");
+
+    // The limit applies to the decompressed body.
+    let zipped = gzip(large.as_bytes());
+    assert!(zipped.len() < 64);
+    let response = app
+        .send(post_txt(zipped, &[("content-encoding", "gzip")]))
+        .await;
+    assert_eq!(response.status, StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn stalled_request_bodies_time_out_with_408() {
+    let app = TestApp::memory_with(config(LayerConfig {
+        timeout: Some(Duration::from_millis(200)),
+        ..Default::default()
+    }))
+    .await;
+    let (mut sender, body) = Body::channel();
+    sender
+        .send_data(Bytes::from_static(b"\"never"))
+        .await
+        .unwrap();
+
+    let response = app.send(post_txt(body, &[])).await;
+    assert_eq!(response.status, StatusCode::REQUEST_TIMEOUT);
+    assert_eq!(response.body["code"], "request_timeout");
+    drop(sender);
+}
+
+#[tokio::test]
+async fn slow_requests_time_out_with_504() {
+    let app = test_app!(config(LayerConfig {
+        timeout: Some(Duration::from_millis(200)),
+        ..Default::default()
+    }));
+    let mut lock = app.pool().begin().await.unwrap();
+    lock.execute("LOCK TABLE test IN ACCESS EXCLUSIVE MODE")
+        .await
+        .unwrap();
+
+    let response = app.get("/txt").await;
+    assert_eq!(response.status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(response.body["code"], "gateway_timeout");
+    lock.rollback().await.unwrap();
+}
@@ -0,0 +1,75 @@
+mod common;
+
+use axum::http::{Method, StatusCode};
+use common::TestApp;
+
+async fn scrape(app: &TestApp) -> String {
+    let response = app.request(Method::GET, "/metrics", &[], None).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(
+        response.header("content-type"),
+        Some("text/plain; version=0.0.4")
+    );
+    // An empty registry renders nothing at all.
+    response.body.as_str().unwrap_or_default().to_owned()
+}
+
+#[tokio::test]
+async fn requests_are_labelled_by_matched_route() {
+    let app = TestApp::memory().await;
+    assert_eq!(app.get("/txt/424242").await.status, StatusCode::NOT_FOUND);
+    let response = app.request(Method::GET, "/no/such/path", &[], None).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    let response = app
+        .request(
+            Method::GET,
+            "/txt",
+            &[("authorization", "Bearer nope")],
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+
+    let metrics = scrape(&app).await;
+    for series in [
+        r#"http_requests_total{method="GET",route="/txt/:id",status="4xx"}"#,
+        r#"http_requests_total{method="GET",route="/txt",status="4xx"}"#,
+        r#"http_requests_total{method="GET",route="unmatched",status="4xx"}"#,
+        r#"http_request_duration_seconds_bucket{method="GET",route="/txt/:id",le="0.005"}"#,
+        r#"http_request_duration_seconds_count{method="GET",route="/txt/:id"}"#,
+    ] {
+        assert!(metrics.contains(series), "missing {series} in\n{metrics}");
+    }
+    assert!(!metrics.contains("424242"), "raw paths must not be labels");
+    assert!(
+        !metrics.contains("/no/such/path"),
+        "raw paths must not be labels"
+    );
+}
+
+#[tokio::test]
+async fn memory_apps_have_no_pool_gauges() {
+    let app = TestApp::memory().await;
+    let metrics = scrape(&app).await;
+    assert!(!metrics.contains("db_pool_connections"));
+}
+
+#[tokio::test]
+async fn pool_gauges_and_query_timings_are_exported() {
+    let app = test_app!();
+    let created = app.create("timed").await;
+    assert_eq!(app.get("/txt").await.status, StatusCode::OK);
+    let response = app.delete(&format!("/txt/{}", created["id"])).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let metrics = scrape(&app).await;
+    for series in [
+        "db_pool_connections ",
+        "db_pool_idle_connections ",
+        r#"db_query_duration_seconds_count{query="create_test"}"#,
+        r#"db_query_duration_seconds_count{query="list_tests"}"#,
+        r#"db_query_duration_seconds_count{query="delete_test"}"#,
+    ] {
+        assert!(metrics.contains(series), "missing {series} in\n{metrics}");
+    }
+}
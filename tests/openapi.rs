@@ -0,0 +1,182 @@
+mod common;
+
+use std::collections::BTreeSet;
+
+use app::RouterConfig;
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode},
+};
+use common::TestApp;
+use serde_json::Value;
+
+const METHODS: [Method; 5] = [
+    Method::GET,
+    Method::POST,
+    Method::PUT,
+    Method::PATCH,
+    Method::DELETE,
+];
+
+/// Every path `router()` serves, written as the spec writes it. Keep it in
+/// step with `router()`.
+const ROUTED_PATHS: [&str; 15] = [
+    "/",
+    "/healthz",
+    "/readyz",
+    "/openapi.json",
+    "/docs",
+    "/metrics",
+    "/admin/config",
+    "/txt",
+    "/txt/search",
+    "/txt/events",
+    "/txt/ws",
+    "/txt/trash",
+    "/txt/bulk",
+    "/txt/{id}",
+    "/txt/{id}/restore",
+];
+
+/// Routed paths deliberately left out of the spec.
+const UNDOCUMENTED_PATHS: [&str; 2] = [
+    // The spec itself.
+    "/openapi.json",
+    // An HTML page rendering the spec.
+    "/docs",
+];
+
+fn spec() -> Value {
+    serde_json::to_value(app::openapi::spec()).unwrap()
+}
+
+/// Documented paths with the methods the spec lists for each.
+fn operations(spec: &Value) -> Vec<(String, Vec<Method>)> {
+    spec["paths"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .map(|(path, item)| {
+            let uri = path.replace("{id}", "1");
+            let methods = METHODS
+                .into_iter()
+                .filter(|method| item.get(method.as_str().to_lowercase()).is_some())
+                .collect();
+            (uri, methods)
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn openapi_json_serves_the_spec() {
+    let app = TestApp::memory().await;
+    let response = app
+        .send(Request::get("/openapi.json").body(Body::empty()).unwrap())
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body, spec());
+    assert!(response.body["openapi"].as_str().unwrap().starts_with("3."));
+    let metrics = &response.body["paths"]["/metrics"]["get"]["responses"]["200"];
+    assert!(metrics["content"]["text/plain"].is_object(), "{metrics}");
+}
+
+#[tokio::test]
+async fn every_documented_operation_is_routed() {
+    let app = TestApp::memory().await;
+    for (uri, methods) in operations(&spec()) {
+        for method in methods {
+            // Event streams never end, so only the status is awaited.
+            let response = app
+                .open(common::request(method.clone(), &uri, &[], None))
+                .await;
+            let status = response.status();
+            assert_ne!(
+                status,
+                StatusCode::METHOD_NOT_ALLOWED,
+                "{method} {uri} is documented but not routed"
+            );
+            // Handlers answer 404 with an error body; unmatched routes are empty.
+            if status == StatusCode::NOT_FOUND {
+                let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+                assert!(
+                    !body.is_empty(),
+                    "{method} {uri} is documented but not routed"
+                );
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn every_routed_path_is_documented() {
+    let spec = spec();
+    let documented: BTreeSet<&str> = spec["paths"]
+        .as_object()
+        .unwrap()
+        .keys()
+        .map(String::as_str)
+        .collect();
+    let expected: BTreeSet<&str> = ROUTED_PATHS
+        .into_iter()
+        .filter(|path| !UNDOCUMENTED_PATHS.contains(path))
+        .collect();
+    assert_eq!(documented, expected);
+
+    // The list must name routes that exist, with `/docs` turned on.
+    let app = TestApp::memory_with(RouterConfig {
+        docs: true,
+        ..Default::default()
+    })
+    .await;
+    for path in ROUTED_PATHS.into_iter().chain(["/not-routed"]) {
+        let uri = path.replace("{id}", "1");
+        let response = app
+            .open(common::request(Method::GET, &uri, &[], None))
+            .await;
+        let status = response.status();
+        let routed = status != StatusCode::NOT_FOUND
+            || !hyper::body::to_bytes(response.into_body())
+                .await
+                .unwrap()
+                .is_empty();
+        assert_eq!(routed, path != "/not-routed", "{path} answered {status}");
+    }
+}
+
+#[tokio::test]
+async fn every_routed_method_is_documented() {
+    let app = TestApp::memory().await;
+    for (uri, methods) in operations(&spec()) {
+        for method in METHODS.into_iter().filter(|m| !methods.contains(m)) {
+            let response = app.request(method.clone(), &uri, &[], None).await;
+            assert_eq!(
+                response.status,
+                StatusCode::METHOD_NOT_ALLOWED,
+                "{method} {uri} is routed but not documented"
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn docs_page_is_opt_in() {
+    let app = TestApp::memory().await;
+    let response = app
+        .send(Request::get("/docs").body(Body::empty()).unwrap())
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+    let app = TestApp::memory_with(RouterConfig {
+        docs: true,
+        ..Default::default()
+    })
+    .await;
+    let response = app
+        .send(Request::get("/docs").body(Body::empty()).unwrap())
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(
+        response.header("content-type"),
+        Some("text/html; charset=utf-8")
+    );
+}
@@ -0,0 +1,234 @@
+mod common;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use app::{config::AppConfig, RouterConfig};
+use axum::http::{Method, StatusCode};
+use common::TestApp;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde_json::{json, Value};
+
+const SECRET: &str = "test-jwt-secret";
+/// Throwaway RSA key pair for RS256 tokens.
+const RSA_PRIVATE_KEY: &str = include_str!("fixtures/jwt_rs256.pem");
+const RSA_PUBLIC_KEY: &str = include_str!("fixtures/jwt_rs256.pub.pem");
+
+fn config() -> RouterConfig {
+    AppConfig::from_lookup(|key| match key {
+        "JWT_HS256_SECRET" => Some(SECRET.to_owned()),
+        "JWT_ISSUER" => Some("tests".to_owned()),
+        _ => None,
+    })
+    .unwrap()
+    .router
+}
+
+/// Settings that only accept RS256 tokens signed with [`RSA_PRIVATE_KEY`].
+fn rs256_config() -> RouterConfig {
+    AppConfig::from_lookup(|key| match key {
+        "JWT_RS256_PUBLIC_KEY" => Some(RSA_PUBLIC_KEY.to_owned()),
+        "JWT_ISSUER" => Some("tests".to_owned()),
+        _ => None,
+    })
+    .unwrap()
+    .router
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+fn token_with(claims: Value, secret: &str) -> String {
+    let key = EncodingKey::from_secret(secret.as_bytes());
+    let token = jsonwebtoken::encode(&Header::default(), &claims, &key).unwrap();
+    format!("Bearer {token}")
+}
+
+fn token(sub: &str) -> String {
+    token_with(
+        json!({ "sub": sub, "iss": "tests", "exp": now() + 60 }),
+        SECRET,
+    )
+}
+
+async fn create_as(app: &TestApp, authorization: &str, txt: &str) -> Value {
+    let response = app
+        .request(
+            Method::POST,
+            "/txt",
+            &[("authorization", authorization)],
+            Some(json!(txt)),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK, "{:?}", response.body);
+    response.body
+}
+
+async fn get_as(app: &TestApp, authorization: &str, uri: &str) -> common::TestResponse {
+    app.request(Method::GET, uri, &[("authorization", authorization)], None)
+        .await
+}
+
+async fn check_rows_are_scoped_to_their_owner(app: TestApp) {
+    let (alice, bob) = (token("alice"), token("bob"));
+    let created = create_as(&app, &alice, "alice's note").await;
+    let uri = format!("/txt/{}", created["id"]);
+
+    let response = get_as(&app, &alice, &uri).await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let response = get_as(&app, &bob, &uri).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    let response = get_as(&app, &bob, "/txt").await;
+    assert_eq!(response.body["items"], json!([]));
+    let response = get_as(&app, &bob, "/txt/search?q=note").await;
+    assert_eq!(response.body, json!([]));
+    let response = app
+        .request(Method::DELETE, &uri, &[("authorization", &bob)], None)
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+    // API keys act as a separate, shared owner.
+    let response = app.get(&uri).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+    let response = get_as(&app, &alice, "/txt").await;
+    assert_eq!(response.body["items"], json!([created]));
+}
+
+#[tokio::test]
+async fn rows_are_scoped_to_their_owner() {
+    check_rows_are_scoped_to_their_owner(TestApp::memory_with(config()).await).await;
+}
+
+#[tokio::test]
+async fn rows_are_scoped_to_their_owner_in_postgres() {
+    check_rows_are_scoped_to_their_owner(test_app!(config())).await;
+}
+
+#[tokio::test]
+async fn bulk_deletes_only_see_own_rows() {
+    let app = TestApp::memory_with(config()).await;
+    let (alice, bob) = (token("alice"), token("bob"));
+    let created = create_as(&app, &alice, "a").await;
+
+    let response = app
+        .request(
+            Method::DELETE,
+            "/txt/bulk",
+            &[("authorization", &bob)],
+            Some(json!([created["id"]])),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+    let response = get_as(&app, &alice, "/txt").await;
+    assert_eq!(response.body["items"], json!([created]));
+}
+
+async fn check_events_are_scoped_to_their_owner(app: TestApp) {
+    let (alice, bob) = (token("alice"), token("bob"));
+    let mut events = app.events(&[("authorization", &bob)]).await;
+
+    create_as(&app, &alice, "alice's note").await;
+    create_as(&app, &bob, "bob's note").await;
+    let event = events.next().await;
+    assert_eq!(event.data["txt"], "bob's note");
+
+    // Replays skip other owners' changes as well.
+    let mut events = app
+        .events(&[("authorization", &bob), ("last-event-id", "0")])
+        .await;
+    assert_eq!(events.next().await.data["txt"], "bob's note");
+    events.assert_idle().await;
+}
+
+#[tokio::test]
+async fn events_are_scoped_to_their_owner() {
+    check_events_are_scoped_to_their_owner(TestApp::memory_with(config()).await).await;
+}
+
+#[tokio::test]
+async fn events_are_scoped_to_their_owner_in_postgres() {
+    check_events_are_scoped_to_their_owner(test_app!(config())).await;
+}
+
+#[tokio::test]
+async fn invalid_tokens_are_401() {
+    let app = TestApp::memory_with(config()).await;
+    for authorization in [
+        token_with(
+            json!({ "sub": "alice", "iss": "tests", "exp": now() - 120 }),
+            SECRET,
+        ),
+        token_with(
+            json!({ "sub": "alice", "iss": "tests", "exp": now() + 60 }),
+            "wrong",
+        ),
+        token_with(
+            json!({ "sub": "alice", "iss": "other", "exp": now() + 60 }),
+            SECRET,
+        ),
+        token_with(json!({ "iss": "tests", "exp": now() + 60 }), SECRET),
+        token_with(json!({ "sub": "alice", "iss": "tests" }), SECRET),
+    ] {
+        let response = get_as(&app, &authorization, "/txt").await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED, "{authorization}");
+        assert_eq!(response.header("www-authenticate"), Some("Bearer"));
+    }
+}
+
+#[tokio::test]
+async fn tokens_are_rejected_when_jwts_are_not_configured() {
+    let app = TestApp::memory().await;
+    let response = get_as(&app, &token("alice"), "/txt").await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+}
+
+fn rs256_token(sub: &str) -> String {
+    let key = EncodingKey::from_rsa_pem(RSA_PRIVATE_KEY.as_bytes()).unwrap();
+    let claims = json!({ "sub": sub, "iss": "tests", "exp": now() + 60 });
+    let token = jsonwebtoken::encode(&Header::new(Algorithm::RS256), &claims, &key).unwrap();
+    format!("Bearer {token}")
+}
+
+#[tokio::test]
+async fn rs256_tokens_are_verified_with_the_public_key() {
+    let app = TestApp::memory_with(rs256_config()).await;
+    let alice = rs256_token("alice");
+    let created = create_as(&app, &alice, "signed with RSA").await;
+
+    let response = get_as(&app, &alice, &format!("/txt/{}", created["id"])).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = get_as(&app, &rs256_token("bob"), "/txt").await;
+    assert_eq!(response.body["items"], json!([]));
+}
+
+#[tokio::test]
+async fn hs256_tokens_are_rejected_when_only_rs256_is_configured() {
+    let app = TestApp::memory_with(rs256_config()).await;
+    let claims = json!({ "sub": "alice", "iss": "tests", "exp": now() + 60 });
+    // Signing with the public key as an HMAC secret is the classic
+    // algorithm confusion attack.
+    for secret in [RSA_PUBLIC_KEY, SECRET] {
+        let authorization = token_with(claims.clone(), secret);
+        let response = get_as(&app, &authorization, "/txt").await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    }
+}
+
+#[test]
+fn invalid_rs256_public_keys_are_reported() {
+    let error = AppConfig::from_lookup(|key| {
+        (key == "JWT_RS256_PUBLIC_KEY").then(|| "-----BEGIN PUBLIC KEY-----\nnot a key".to_owned())
+    })
+    .unwrap_err();
+    assert_eq!(error.0.len(), 1);
+    assert!(
+        error.0[0].starts_with("JWT_RS256_PUBLIC_KEY: invalid RS256 public key"),
+        "{error}"
+    );
+}
@@ -0,0 +1,214 @@
+mod common;
+
+use std::time::Duration;
+
+use app::{
+    auth::AuthConfig,
+    config::AppConfig,
+    rate_limit::{Quota, RateLimitConfig},
+    RouterConfig,
+};
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode},
+};
+use common::{TestApp, READ_KEY};
+use serde_json::json;
+
+fn limited(read: Quota, write: Quota) -> RouterConfig {
+    RouterConfig {
+        rate_limit: Some(RateLimitConfig {
+            read,
+            write,
+            trust_proxy: false,
+        }),
+        ..Default::default()
+    }
+}
+
+fn quota(burst: u32) -> Quota {
+    Quota {
+        burst,
+        per_minute: 1,
+    }
+}
+
+#[tokio::test]
+async fn spent_budgets_are_429() {
+    let app = TestApp::memory_with(limited(quota(2), quota(2))).await;
+
+    let response = app.get("/txt").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("ratelimit-limit"), Some("2"));
+    assert_eq!(response.header("ratelimit-remaining"), Some("1"));
+    assert_eq!(response.header("ratelimit-reset"), Some("60"));
+    assert_eq!(response.header("retry-after"), None);
+
+    app.get("/txt").await;
+    let response = app.get("/txt").await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.body["code"], "rate_limited");
+    assert_eq!(response.header("ratelimit-remaining"), Some("0"));
+    assert_eq!(response.header("retry-after"), Some("60"));
+}
+
+#[tokio::test]
+async fn reads_and_writes_have_separate_budgets() {
+    let app = TestApp::memory_with(limited(quota(1), quota(1))).await;
+
+    app.post("/txt", json!("a")).await;
+    let response = app.post("/txt", json!("b")).await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+
+    let response = app.get("/txt").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body["items"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn keys_have_separate_budgets() {
+    let app = TestApp::memory_with(limited(quota(1), quota(1))).await;
+    let read = format!("Bearer {READ_KEY}");
+
+    app.get("/txt").await;
+    let response = app.get("/txt").await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+
+    let response = app
+        .request(Method::GET, "/txt", &[("authorization", &read)], None)
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn anonymous_clients_are_told_apart_by_ip() {
+    let app = TestApp::memory_with(RouterConfig {
+        auth: AuthConfig {
+            public_reads: true,
+            ..Default::default()
+        },
+        ..limited(quota(1), quota(1))
+    })
+    .await;
+    let get_from = |forwarded_for: &'static str| {
+        Request::get("/txt")
+            .header("x-forwarded-for", forwarded_for)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let response = app.send(get_from("10.0.0.1")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    // Only the address appended by the proxy counts.
+    let response = app.send(get_from("192.0.2.7, 10.0.0.1")).await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    let response = app.send(get_from("10.0.0.2")).await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn anonymous_clients_without_an_address_are_not_limited() {
+    let app = TestApp::memory_with(RouterConfig {
+        auth: AuthConfig {
+            public_reads: true,
+            ..Default::default()
+        },
+        ..limited(quota(1), quota(1))
+    })
+    .await;
+    for _ in 0..3 {
+        let response = app
+            .send(Request::get("/txt").body(Body::empty()).unwrap())
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.header("ratelimit-limit"), None);
+    }
+}
+
+#[tokio::test]
+async fn failed_authentication_spends_the_address_budget() {
+    let app = TestApp::memory_with(limited(quota(5), quota(2))).await;
+    let get_from = |forwarded_for: &'static str, key: &str| {
+        Request::get("/txt")
+            .header("x-forwarded-for", forwarded_for)
+            .header("authorization", format!("Bearer {key}"))
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    for _ in 0..2 {
+        let response = app.send(get_from("10.0.0.1", "guess")).await;
+        assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    }
+    let response = app.send(get_from("10.0.0.1", "guess")).await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.header("retry-after"), Some("60"));
+    let response = app.send(get_from("10.0.0.1", READ_KEY)).await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+
+    let response = app.send(get_from("10.0.0.2", "guess")).await;
+    assert_eq!(response.status, StatusCode::UNAUTHORIZED);
+    // Authenticated requests do not spend it.
+    for _ in 0..3 {
+        let response = app.send(get_from("10.0.0.2", READ_KEY)).await;
+        assert_eq!(response.status, StatusCode::OK);
+    }
+}
+
+#[tokio::test]
+async fn budgets_refill_over_time() {
+    let fast = Quota {
+        burst: 1,
+        per_minute: 6000,
+    };
+    let app = TestApp::memory_with(limited(fast, fast)).await;
+
+    app.get("/txt").await;
+    let response = app.get("/txt").await;
+    assert_eq!(response.status, StatusCode::TOO_MANY_REQUESTS);
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let response = app.get("/txt").await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn health_routes_are_not_limited() {
+    let app = TestApp::memory_with(limited(quota(1), quota(1))).await;
+    for _ in 0..3 {
+        let response = app
+            .send(Request::get("/healthz").body(Body::empty()).unwrap())
+            .await;
+        assert_eq!(response.status, StatusCode::OK);
+        assert_eq!(response.header("ratelimit-limit"), None);
+    }
+}
+
+#[test]
+fn config_is_read_from_settings() {
+    let config = AppConfig::from_lookup(|key| match key {
+        "RATE_LIMIT_WRITE_BURST" => Some("5".to_owned()),
+        "RATE_LIMIT_WRITE_PER_MINUTE" => Some("30".to_owned()),
+        _ => None,
+    })
+    .unwrap()
+    .router
+    .rate_limit
+    .unwrap();
+    assert_eq!(
+        config.write,
+        Quota {
+            burst: 5,
+            per_minute: 30
+        }
+    );
+    assert_eq!(config.read, RateLimitConfig::default().read);
+
+    let disabled =
+        AppConfig::from_lookup(|key| (key == "RATE_LIMIT_ENABLED").then(|| "false".to_owned()));
+    assert_eq!(disabled.unwrap().router.rate_limit, None);
+
+    let invalid =
+        AppConfig::from_lookup(|key| (key == "RATE_LIMIT_READ_BURST").then(|| "0".to_owned()));
+    assert!(invalid.is_err());
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use axum::http::{Method, StatusCode};
+use common::TestApp;
+use shuttle_runtime::tracing::{
+    field::{Field, Visit},
+    instrument::WithSubscriber,
+    span, Subscriber,
+};
+use tracing_subscriber::{layer::Context, prelude::*, registry::LookupSpan, Layer};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn generated_ids_are_returned_and_reported_in_errors() {
+    let app = TestApp::memory().await;
+    let response = app.get("/txt/424242").await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+
+    let id = response
+        .header("x-request-id")
+        .expect("missing X-Request-Id");
+    assert!(Uuid::parse_str(id).is_ok(), "{id} is not a uuid");
+    assert_eq!(response.body["request_id"], id);
+
+    let other = app.get("/txt/424242").await;
+    assert_ne!(other.header("x-request-id"), Some(id));
+}
+
+#[tokio::test]
+async fn client_ids_are_kept() {
+    let app = TestApp::memory().await;
+    let headers = [("x-request-id", "edge-7f3a.1")];
+    let response = app.request(Method::GET, "/txt", &headers, None).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.header("x-request-id"), Some("edge-7f3a.1"));
+
+    let response = app
+        .request(Method::GET, "/txt/424242", &headers, None)
+        .await;
+    assert_eq!(response.body["request_id"], "edge-7f3a.1");
+}
+
+#[tokio::test]
+async fn unreasonable_client_ids_are_replaced() {
+    let app = TestApp::memory().await;
+    let long = "x".repeat(129);
+    for id in ["", "has space", long.as_str()] {
+        let response = app
+            .request(Method::GET, "/txt", &[("x-request-id", id)], None)
+            .await;
+        let returned = response.header("x-request-id").unwrap();
+        assert!(Uuid::parse_str(returned).is_ok(), "{id:?} was kept");
+    }
+}
+
+#[derive(Debug)]
+struct RecordedSpan {
+    id: span::Id,
+    name: &'static str,
+    parent: Option<span::Id>,
+    fields: HashMap<&'static str, String>,
+}
+
+impl RecordedSpan {
+    fn field(&self, name: &str) -> Option<&str> {
+        self.fields.get(name).map(String::as_str)
+    }
+}
+
+struct Fields<'a>(&'a mut HashMap<&'static str, String>);
+
+impl Visit for Fields<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name(), value.to_owned());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name(), format!("{value:?}"));
+    }
+}
+
+/// Keeps every span created while it is the subscriber. Ids of closed spans
+/// are reused, so the latest span with an id is the one it refers to.
+#[derive(Clone, Default)]
+struct Spans(Arc<Mutex<Vec<RecordedSpan>>>);
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Spans {
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let mut span = RecordedSpan {
+            id: id.clone(),
+            name: attrs.metadata().name(),
+            parent: ctx.span(id).and_then(|span| span.parent()).map(|p| p.id()),
+            fields: HashMap::new(),
+        };
+        attrs.record(&mut Fields(&mut span.fields));
+        self.0.lock().unwrap().push(span);
+    }
+
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, _ctx: Context<'_, S>) {
+        let mut spans = self.0.lock().unwrap();
+        if let Some(span) = spans.iter_mut().rev().find(|span| span.id == *id) {
+            values.record(&mut Fields(&mut span.fields));
+        }
+    }
+}
+
+#[tokio::test]
+async fn requests_and_their_queries_are_traced() {
+    let app = test_app!();
+    app.create("traced").await;
+
+    let spans = Spans::default();
+    let subscriber = tracing_subscriber::registry().with(spans.clone());
+    let response = app
+        .request(Method::GET, "/txt", &[("x-request-id", "trace-me")], None)
+        .with_subscriber(subscriber)
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    let spans = spans.0.lock().unwrap();
+    let request = spans
+        .iter()
+        .find(|span| span.name == "request")
+        .expect("no request span");
+    assert_eq!(request.field("request_id"), Some("trace-me"));
+    assert_eq!(request.field("http.route"), Some("/txt"));
+    assert_eq!(request.field("http.status_code"), Some("200"));
+    assert_eq!(request.field("otel.name"), Some("GET /txt"));
+
+    let list = spans
+        .iter()
+        .find(|span| {
+            span.name == "sql"
+                && span
+                    .field("db.statement")
+                    .is_some_and(|sql| sql.starts_with("SELECT id, txt") && sql.contains("LIMIT"))
+        })
+        .expect("no span for the list query");
+    assert_eq!(list.parent.as_ref(), Some(&request.id));
+    assert_eq!(list.field("db.rows"), Some("1"));
+}
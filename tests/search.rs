@@ -0,0 +1,48 @@
+mod common;
+
+use axum::http::StatusCode;
+use serde_json::json;
+
+#[tokio::test]
+async fn search_ranks_and_highlights_matches() {
+    let app = test_app!();
+    app.post(
+        "/txt/bulk",
+        json!(["shuttle rocks", "axum & shuttle", "nothing here"]),
+    )
+    .await;
+
+    let response = app.get("/txt/search?q=shuttle").await;
+    assert_eq!(response.status, StatusCode::OK);
+    let hits = response.body.as_array().unwrap();
+    assert_eq!(hits.len(), 2);
+    assert!(hits[0]["rank"].as_f64().unwrap() > 0.0);
+    assert_eq!(hits[1]["snippet"], "axum &amp; <mark>shuttle</mark>");
+}
+
+#[tokio::test]
+async fn search_supports_prefixes_and_phrases() {
+    let app = test_app!();
+    app.post(
+        "/txt/bulk",
+        json!(["shuttle rocks", "rocks shuttle", "axum"]),
+    )
+    .await;
+
+    let response = app.get("/txt/search?q=ax*").await;
+    assert_eq!(response.body[0]["txt"], "axum");
+
+    let response = app.get("/txt/search?q=%22shuttle%20rocks%22").await;
+    let hits = response.body.as_array().unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0]["txt"], "shuttle rocks");
+}
+
+#[tokio::test]
+async fn search_without_terms_is_400() {
+    let app = test_app!();
+    for uri in ["/txt/search?q=%26%7C%21", "/txt/search"] {
+        let response = app.get(uri).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST, "{uri}");
+    }
+}
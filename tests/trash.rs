@@ -0,0 +1,146 @@
+mod common;
+
+use app::{
+    config::AppConfig,
+    repository::{MemoryRepository, PgRepository},
+    trash::{self, PurgeConfig},
+};
+use axum::http::{Method, StatusCode};
+use common::TestApp;
+use serde_json::{json, Value};
+
+fn txts(page: &Value) -> Vec<&str> {
+    page["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["txt"].as_str().unwrap())
+        .collect()
+}
+
+async fn restore(app: &TestApp, id: &Value) -> common::TestResponse {
+    app.request(Method::POST, &format!("/txt/{id}/restore"), &[], None)
+        .await
+}
+
+async fn check_deleted_rows_move_to_the_trash(app: TestApp) {
+    let kept = app.create("kept").await;
+    let deleted = app.create("deleted").await;
+    let uri = format!("/txt/{}", deleted["id"]);
+
+    let response = app.delete(&uri).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(app.get(&uri).await.status, StatusCode::NOT_FOUND);
+    assert_eq!(app.delete(&uri).await.status, StatusCode::NOT_FOUND);
+    assert_eq!(txts(&app.get("/txt").await.body), ["kept"]);
+    assert_eq!(app.get("/txt/search?q=deleted").await.body, json!([]));
+
+    let response = app.get("/txt/trash").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(txts(&response.body), ["deleted"]);
+    assert_eq!(response.body["items"][0]["id"], deleted["id"]);
+    assert!(response.body["items"][0]["deleted_at"].is_string());
+
+    // Restoring counts as an update, so clients syncing with `since` see it.
+    let response = restore(&app, &deleted["id"]).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body["txt"], "deleted");
+    assert_eq!(response.body["version"], 2);
+    assert_eq!(response.header("etag"), Some("\"2\""));
+    assert_eq!(app.get(&uri).await.status, StatusCode::OK);
+    assert_eq!(app.get("/txt/trash").await.body["items"], json!([]));
+
+    // Only trashed rows can be restored.
+    let response = restore(&app, &kept["id"]).await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert_eq!(response.body["code"], "not_found");
+}
+
+#[tokio::test]
+async fn deleted_rows_move_to_the_trash() {
+    check_deleted_rows_move_to_the_trash(test_app!()).await;
+}
+
+#[tokio::test]
+async fn deleted_rows_move_to_the_trash_in_memory() {
+    check_deleted_rows_move_to_the_trash(TestApp::memory().await).await;
+}
+
+#[tokio::test]
+async fn trash_pages_and_filters_like_the_list() {
+    let app = TestApp::memory().await;
+    let created = app.post("/txt/bulk", json!(["a1", "b", "a2", "a3"])).await;
+    let ids: Vec<&Value> = created.body["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| &item["id"])
+        .collect();
+    app.request(Method::DELETE, "/txt/bulk", &[], Some(json!(ids)))
+        .await;
+
+    let response = app.get("/txt/trash?q=a&order=desc&limit=2").await;
+    assert_eq!(txts(&response.body), ["a3", "a2"]);
+    let cursor = response.body["next_cursor"].as_str().unwrap();
+    let response = app
+        .get(&format!(
+            "/txt/trash?q=a&order=desc&limit=2&cursor={cursor}"
+        ))
+        .await;
+    assert_eq!(txts(&response.body), ["a1"]);
+    assert_eq!(response.body["next_cursor"], Value::Null);
+}
+
+#[tokio::test]
+async fn purge_removes_rows_trashed_before_the_retention() {
+    let app = test_app!();
+    let old = app.create("old").await;
+    let recent = app.create("recent").await;
+    let live = app.create("live").await;
+    for test in [&old, &recent] {
+        app.delete(&format!("/txt/{}", test["id"])).await;
+    }
+    sqlx::query("UPDATE test SET deleted_at = now() - interval '31 days' WHERE id = $1")
+        .bind(old["id"].as_i64().unwrap() as i32)
+        .execute(app.pool())
+        .await
+        .unwrap();
+
+    let repo = PgRepository::new(app.pool().clone());
+    let purged = trash::purge(&repo, PurgeConfig::default()).await.unwrap();
+    assert_eq!(purged, 1);
+
+    assert_eq!(txts(&app.get("/txt/trash").await.body), ["recent"]);
+    assert_eq!(
+        restore(&app, &old["id"]).await.status,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        app.get(&format!("/txt/{}", live["id"])).await.status,
+        StatusCode::OK
+    );
+}
+
+#[tokio::test]
+async fn purge_keeps_everything_when_the_retention_predates_all_dates() {
+    let repo = MemoryRepository::new();
+    let config = PurgeConfig {
+        retention: chrono::Duration::days(1_000_000_000),
+    };
+    assert_eq!(trash::purge(&repo, config).await.unwrap(), 0);
+}
+
+#[test]
+fn retention_is_read_from_settings() {
+    let config =
+        AppConfig::from_lookup(|key| (key == "TRASH_RETENTION_DAYS").then(|| "7".to_owned()));
+    assert_eq!(config.unwrap().purge.retention, chrono::Duration::days(7));
+    assert_eq!(
+        AppConfig::from_lookup(|_| None).unwrap().purge,
+        PurgeConfig::default()
+    );
+    assert!(
+        AppConfig::from_lookup(|key| (key == "TRASH_RETENTION_DAYS").then(|| "-1".to_owned()))
+            .is_err()
+    );
+}
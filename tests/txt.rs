@@ -0,0 +1,400 @@
+mod common;
+
+use app::error::AppError;
+use axum::{
+    http::{Method, StatusCode},
+    response::IntoResponse,
+};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn root_says_hello() {
+    let app = test_app!();
+    let response = app.get("/").await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body, "Hello Axum! Shuttle rocks!");
+}
+
+#[tokio::test]
+async fn create_trims_and_returns_the_row() {
+    let app = test_app!();
+    let response = app.post("/txt", json!("  hello  ")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body["txt"], "hello");
+    assert_eq!(response.body["version"], 1);
+}
+
+#[tokio::test]
+async fn create_rejects_invalid_txt() {
+    let app = test_app!();
+
+    let response = app.post("/txt", json!("   ")).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.body["code"], "validation_failed");
+    assert_eq!(response.body["errors"][0]["field"], "txt");
+
+    // 255 multi-byte characters fit, 256 do not.
+    let response = app.post("/txt", json!("é".repeat(255))).await;
+    assert_eq!(response.status, StatusCode::OK);
+    let response = app.post("/txt", json!("é".repeat(256))).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn nul_characters_are_rejected() {
+    let app = test_app!();
+    let response = app.post("/txt", json!("a\u{0}b")).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.body["code"], "validation_failed");
+    assert_eq!(response.body["errors"][0]["field"], "txt");
+
+    let created = app.create("hello").await;
+    let response = app
+        .request(
+            Method::PATCH,
+            &format!("/txt/{}", created["id"]),
+            &[("if-match", "*")],
+            Some(json!("a\u{0}b")),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+
+    let response = app
+        .post("/txt/bulk?mode=atomic", json!(["a", "a\u{0}b"]))
+        .await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.body["errors"][0]["field"], "[1]");
+}
+
+#[tokio::test]
+async fn nul_characters_reaching_postgres_are_client_errors() {
+    let app = test_app!();
+    let error = sqlx::query("INSERT INTO test (txt) VALUES ($1)")
+        .bind("a\u{0}b")
+        .execute(app.pool())
+        .await
+        .unwrap_err();
+    let response = AppError::from(error).into_response();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn create_rejects_malformed_bodies() {
+    let app = test_app!();
+
+    let response = app.post("/txt", json!({ "txt": "not a string" })).await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(response.body["code"], "invalid_body");
+
+    let response = app.request(Method::POST, "/txt", &[], None).await;
+    assert_eq!(response.status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(response.body["code"], "invalid_body");
+    assert!(response.body["request_id"].is_string());
+}
+
+#[tokio::test]
+async fn get_returns_the_row_with_its_etag() {
+    let app = test_app!();
+    let created = app.create("hello").await;
+
+    let response = app.get(&format!("/txt/{}", created["id"])).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body, created);
+    assert_eq!(response.header("etag"), Some("\"1\""));
+    assert!(response.header("last-modified").unwrap().ends_with(" GMT"));
+}
+
+#[tokio::test]
+async fn get_with_current_etag_is_304() {
+    let app = test_app!();
+    let created = app.create("hello").await;
+    let uri = format!("/txt/{}", created["id"]);
+
+    for if_none_match in ["\"1\"", "W/\"1\"", "\"7\", \"1\"", "*"] {
+        let response = app
+            .request(Method::GET, &uri, &[("if-none-match", if_none_match)], None)
+            .await;
+        assert_eq!(response.status, StatusCode::NOT_MODIFIED, "{if_none_match}");
+        assert_eq!(response.body, Value::Null);
+        assert_eq!(response.header("etag"), Some("\"1\""));
+    }
+
+    app.request(Method::PUT, &uri, &[("if-match", "*")], Some(json!("bye")))
+        .await;
+    let response = app
+        .request(Method::GET, &uri, &[("if-none-match", "\"1\"")], None)
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body["txt"], "bye");
+}
+
+#[tokio::test]
+async fn get_unmodified_since_last_modified_is_304() {
+    let app = test_app!();
+    let created = app.create("hello").await;
+    let uri = format!("/txt/{}", created["id"]);
+    let last_modified = app
+        .get(&uri)
+        .await
+        .header("last-modified")
+        .unwrap()
+        .to_owned();
+
+    let response = app
+        .request(
+            Method::GET,
+            &uri,
+            &[("if-modified-since", &last_modified)],
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::NOT_MODIFIED);
+    assert_eq!(
+        response.header("last-modified"),
+        Some(last_modified.as_str())
+    );
+
+    let before = "Sat, 01 Jan 2000 00:00:00 GMT";
+    let response = app
+        .request(Method::GET, &uri, &[("if-modified-since", before)], None)
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+
+    // If-None-Match takes precedence over If-Modified-Since.
+    let response = app
+        .request(
+            Method::GET,
+            &uri,
+            &[
+                ("if-none-match", "\"7\""),
+                ("if-modified-since", &last_modified),
+            ],
+            None,
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn get_missing_row_is_404() {
+    let app = test_app!();
+    let response = app.get("/txt/4242").await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert_eq!(response.body["code"], "not_found");
+}
+
+#[tokio::test]
+async fn get_with_non_numeric_id_is_400() {
+    let app = test_app!();
+    let response = app.get("/txt/abc").await;
+    assert_eq!(response.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn update_with_matching_version_bumps_it() {
+    let app = test_app!();
+    let created = app.create("hello").await;
+    let uri = format!("/txt/{}", created["id"]);
+
+    for (method, txt, if_match, version) in [
+        (Method::PUT, "put", "\"1\"", 2),
+        (Method::PATCH, "patch", "\"7\", \"2\"", 3),
+        (Method::PUT, "any", "*", 4),
+    ] {
+        let response = app
+            .request(method, &uri, &[("if-match", if_match)], Some(json!(txt)))
+            .await;
+        assert_eq!(response.status, StatusCode::OK, "{:?}", response.body);
+        assert_eq!(response.body["txt"], txt);
+        assert_eq!(response.body["version"], version);
+        assert_eq!(
+            response.header("etag"),
+            Some(format!("\"{version}\"").as_str())
+        );
+    }
+}
+
+#[tokio::test]
+async fn update_with_stale_version_is_412() {
+    let app = test_app!();
+    let created = app.create("hello").await;
+    let uri = format!("/txt/{}", created["id"]);
+
+    let first = app
+        .request(
+            Method::PUT,
+            &uri,
+            &[("if-match", "\"1\"")],
+            Some(json!("a")),
+        )
+        .await;
+    assert_eq!(first.status, StatusCode::OK);
+
+    let second = app
+        .request(
+            Method::PUT,
+            &uri,
+            &[("if-match", "\"1\"")],
+            Some(json!("b")),
+        )
+        .await;
+    assert_eq!(second.status, StatusCode::PRECONDITION_FAILED);
+    assert_eq!(second.body["code"], "precondition_failed");
+    assert_eq!(app.get(&uri).await.body["txt"], "a");
+}
+
+#[tokio::test]
+async fn update_without_if_match_is_428() {
+    let app = test_app!();
+    let created = app.create("hello").await;
+    let response = app
+        .request(
+            Method::PUT,
+            &format!("/txt/{}", created["id"]),
+            &[],
+            Some(json!("a")),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::PRECONDITION_REQUIRED);
+}
+
+#[tokio::test]
+async fn update_missing_row_is_404() {
+    let app = test_app!();
+    for if_match in ["\"1\"", "*"] {
+        let response = app
+            .request(
+                Method::PUT,
+                "/txt/4242",
+                &[("if-match", if_match)],
+                Some(json!("a")),
+            )
+            .await;
+        assert_eq!(response.status, StatusCode::NOT_FOUND);
+    }
+}
+
+#[tokio::test]
+async fn update_validates_txt() {
+    let app = test_app!();
+    let created = app.create("hello").await;
+    let response = app
+        .request(
+            Method::PATCH,
+            &format!("/txt/{}", created["id"]),
+            &[("if-match", "*")],
+            Some(json!("")),
+        )
+        .await;
+    assert_eq!(response.status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn delete_removes_the_row() {
+    let app = test_app!();
+    let created = app.create("hello").await;
+    let uri = format!("/txt/{}", created["id"]);
+
+    let response = app.delete(&uri).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(response.body, created);
+    assert_eq!(app.get(&uri).await.status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn delete_missing_row_is_404() {
+    let app = test_app!();
+    let response = app.delete("/txt/4242").await;
+    assert_eq!(response.status, StatusCode::NOT_FOUND);
+    assert_eq!(response.body["code"], "not_found");
+}
+
+fn txts(page: &Value) -> Vec<&str> {
+    page["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["txt"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn list_pages_through_all_rows() {
+    let app = test_app!();
+    for txt in ["a", "b", "c", "d", "e"] {
+        app.create(txt).await;
+    }
+
+    let first = app.get("/txt?limit=2").await;
+    assert_eq!(first.status, StatusCode::OK);
+    assert_eq!(txts(&first.body), ["a", "b"]);
+
+    let cursor = first.body["next_cursor"].as_str().unwrap();
+    let second = app.get(&format!("/txt?limit=2&cursor={cursor}")).await;
+    assert_eq!(txts(&second.body), ["c", "d"]);
+
+    let cursor = second.body["next_cursor"].as_str().unwrap();
+    let last = app.get(&format!("/txt?limit=2&cursor={cursor}")).await;
+    assert_eq!(txts(&last.body), ["e"]);
+    assert_eq!(last.body["next_cursor"], Value::Null);
+}
+
+#[tokio::test]
+async fn list_sorts_descending_and_filters() {
+    let app = test_app!();
+    for txt in ["apple", "banana", "Pineapple", "cherry"] {
+        app.create(txt).await;
+    }
+
+    let response = app.get("/txt?order=desc").await;
+    assert_eq!(
+        txts(&response.body),
+        ["cherry", "Pineapple", "banana", "apple"]
+    );
+
+    let response = app.get("/txt?q=APPLE&order=desc&limit=1").await;
+    assert_eq!(txts(&response.body), ["Pineapple"]);
+    let cursor = response.body["next_cursor"].as_str().unwrap();
+    let response = app
+        .get(&format!("/txt?q=APPLE&order=desc&limit=1&cursor={cursor}"))
+        .await;
+    assert_eq!(txts(&response.body), ["apple"]);
+}
+
+#[tokio::test]
+async fn list_rejects_bad_parameters() {
+    let app = test_app!();
+    for uri in [
+        "/txt?cursor=not-a-cursor",
+        "/txt?limit=0",
+        "/txt?limit=101",
+        "/txt?order=sideways",
+        "/txt?since=yesterday",
+    ] {
+        let response = app.get(uri).await;
+        assert_eq!(response.status, StatusCode::BAD_REQUEST, "{uri}");
+        assert_eq!(response.body["code"], "bad_request");
+    }
+}
+
+#[tokio::test]
+async fn list_since_returns_rows_updated_later() {
+    let app = test_app!();
+    let first = app.create("first").await;
+    let second = app.create("second").await;
+
+    let since = second["updated_at"].as_str().unwrap();
+    let response = app.get(&format!("/txt?since={since}")).await;
+    assert_eq!(response.status, StatusCode::OK);
+    assert_eq!(txts(&response.body), Vec::<&str>::new());
+
+    app.request(
+        Method::PUT,
+        &format!("/txt/{}", first["id"]),
+        &[("if-match", "*")],
+        Some(json!("first, again")),
+    )
+    .await;
+    let response = app.get(&format!("/txt?since={since}")).await;
+    assert_eq!(txts(&response.body), ["first, again"]);
+}
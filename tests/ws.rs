@@ -0,0 +1,239 @@
+mod common;
+
+use std::time::Duration;
+
+use app::{
+    rate_limit::{Quota, RateLimitConfig},
+    RouterConfig,
+};
+use axum::http::{header, HeaderValue, StatusCode};
+use common::{TestApp, API_KEY, READ_KEY};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    tungstenite::{client::IntoClientRequest, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+struct Client {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl Client {
+    async fn connect(app: &TestApp, key: &str) -> Self {
+        let addr = app.serve().await;
+        let mut request = format!("ws://{addr}/txt/ws").into_client_request().unwrap();
+        request.headers_mut().insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {key}")).unwrap(),
+        );
+        let (socket, response) = tokio_tungstenite::connect_async(request)
+            .await
+            .expect("failed to open the WebSocket");
+        assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+        Client { socket }
+    }
+
+    async fn send(&mut self, frame: impl ToString) {
+        self.socket
+            .send(Message::Text(frame.to_string()))
+            .await
+            .unwrap();
+    }
+
+    /// The next text frame as JSON. Panics when none arrives in time.
+    async fn recv(&mut self) -> Value {
+        loop {
+            let message = tokio::time::timeout(Duration::from_secs(5), self.socket.next())
+                .await
+                .expect("timed out waiting for a frame")
+                .expect("the WebSocket closed")
+                .unwrap();
+            if let Message::Text(text) = message {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    async fn command(&mut self, frame: Value) -> Value {
+        self.send(frame).await;
+        self.recv().await
+    }
+}
+
+async fn check_commands_are_acknowledged(app: TestApp) {
+    let mut client = Client::connect(&app, API_KEY).await;
+
+    let ack = client
+        .command(json!({ "type": "create", "id": "1", "txt": " hello " }))
+        .await;
+    assert_eq!(ack["type"], "ack");
+    assert_eq!(ack["id"], "1");
+    assert_eq!(ack["test"]["txt"], "hello");
+    let id = ack["test"]["id"].clone();
+    assert_eq!(app.get(&format!("/txt/{id}")).await.status, StatusCode::OK);
+
+    let ack = client
+        .command(json!({ "type": "delete", "id": 2, "test_id": id }))
+        .await;
+    assert_eq!(ack, json!({ "type": "ack", "id": 2, "test": ack["test"] }));
+    assert_eq!(ack["test"]["id"], id);
+    assert_eq!(
+        app.get(&format!("/txt/{id}")).await.status,
+        StatusCode::NOT_FOUND
+    );
+
+    let error = client
+        .command(json!({ "type": "delete", "id": "3", "test_id": id }))
+        .await;
+    assert_eq!(error["type"], "error");
+    assert_eq!(error["id"], "3");
+    assert_eq!(error["error"]["code"], "not_found");
+}
+
+#[tokio::test]
+async fn commands_are_acknowledged() {
+    check_commands_are_acknowledged(test_app!()).await;
+}
+
+#[tokio::test]
+async fn commands_are_acknowledged_in_memory() {
+    check_commands_are_acknowledged(TestApp::memory().await).await;
+}
+
+#[tokio::test]
+async fn invalid_frames_get_error_frames() {
+    let app = TestApp::memory().await;
+    let mut client = Client::connect(&app, API_KEY).await;
+
+    client.send("not json").await;
+    let error = client.recv().await;
+    assert_eq!(error["id"], Value::Null);
+    assert_eq!(error["error"]["code"], "bad_request");
+
+    let error = client.command(json!({ "type": "shout", "id": 1 })).await;
+    assert_eq!(error["id"], 1);
+    assert_eq!(error["error"]["code"], "bad_request");
+
+    let error = client
+        .command(json!({ "type": "create", "id": 2, "txt": "   " }))
+        .await;
+    assert_eq!(error["id"], 2);
+    assert_eq!(error["error"]["code"], "validation_failed");
+    assert_eq!(error["error"]["errors"][0]["field"], "txt");
+
+    // The connection survives bad frames.
+    let ack = client
+        .command(json!({ "type": "create", "id": 3, "txt": "fine" }))
+        .await;
+    assert_eq!(ack["type"], "ack");
+}
+
+async fn check_subscribers_receive_changes(app: TestApp) {
+    let mut client = Client::connect(&app, API_KEY).await;
+    let ack = client
+        .command(json!({ "type": "subscribe", "id": 1 }))
+        .await;
+    assert_eq!(ack, json!({ "type": "ack", "id": 1 }));
+
+    app.create("over http").await;
+    let change = client.recv().await;
+    assert_eq!(change["type"], "change");
+    assert_eq!(change["kind"], "created");
+    assert_eq!(change["test"]["txt"], "over http");
+    let first = change["event_id"].as_i64().unwrap();
+
+    let ack = client
+        .command(json!({ "type": "create", "id": 2, "txt": "over ws" }))
+        .await;
+    assert_eq!(ack["type"], "ack");
+    let change = client.recv().await;
+    assert_eq!(change["test"]["txt"], "over ws");
+    assert!(change["event_id"].as_i64().unwrap() > first);
+
+    let ack = client
+        .command(json!({ "type": "unsubscribe", "id": 3 }))
+        .await;
+    assert_eq!(ack["type"], "ack");
+    let unseen = app.create("unseen").await;
+    let ack = client
+        .command(json!({ "type": "delete", "id": 4, "test_id": unseen["id"] }))
+        .await;
+    assert_eq!(ack["id"], 4, "no change frame after unsubscribing");
+}
+
+#[tokio::test]
+async fn subscribers_receive_changes() {
+    check_subscribers_receive_changes(test_app!()).await;
+}
+
+#[tokio::test]
+async fn subscribers_receive_changes_in_memory() {
+    check_subscribers_receive_changes(TestApp::memory().await).await;
+}
+
+#[tokio::test]
+async fn subscriptions_resume_after_last_event_id() {
+    let app = TestApp::memory().await;
+    app.create("seen").await;
+    app.create("missed").await;
+
+    let mut client = Client::connect(&app, API_KEY).await;
+    let ack = client
+        .command(json!({ "type": "subscribe", "id": 1, "last_event_id": 1 }))
+        .await;
+    assert_eq!(ack["type"], "ack");
+    let change = client.recv().await;
+    assert_eq!(change["event_id"], 2);
+    assert_eq!(change["test"]["txt"], "missed");
+
+    let ack = client
+        .command(json!({ "type": "subscribe", "id": 2, "last_event_id": 99 }))
+        .await;
+    assert_eq!(ack["type"], "ack");
+    assert_eq!(
+        client.recv().await,
+        json!({ "type": "reset", "event_id": 2 })
+    );
+}
+
+#[tokio::test]
+async fn read_only_keys_can_subscribe_but_not_write() {
+    let app = TestApp::memory().await;
+    let mut client = Client::connect(&app, READ_KEY).await;
+
+    let error = client
+        .command(json!({ "type": "create", "id": 1, "txt": "nope" }))
+        .await;
+    assert_eq!(error["error"]["code"], "forbidden");
+    let ack = client
+        .command(json!({ "type": "subscribe", "id": 2 }))
+        .await;
+    assert_eq!(ack["type"], "ack");
+}
+
+#[tokio::test]
+async fn commands_count_against_the_rate_limit() {
+    let app = TestApp::memory_with(RouterConfig {
+        rate_limit: Some(RateLimitConfig {
+            write: Quota {
+                burst: 1,
+                per_minute: 1,
+            },
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+    .await;
+    let mut client = Client::connect(&app, API_KEY).await;
+
+    let ack = client
+        .command(json!({ "type": "create", "id": 1, "txt": "first" }))
+        .await;
+    assert_eq!(ack["type"], "ack");
+    let error = client
+        .command(json!({ "type": "create", "id": 2, "txt": "second" }))
+        .await;
+    assert_eq!(error["error"]["code"], "rate_limited");
+}